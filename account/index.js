@@ -52,43 +52,398 @@ const createKeypairFromFile = async (filePath) => {
 }
 
 /**
- * The state of a vote account managed by the vote program
+ * A vote option of a poll and its counter
  */
-class VoteAccount {
-    yes = 0;
-    abstained = 0;
-    no = 0;
+class VoteOption {
+    label = '';
+    votes = 0;
 
     constructor(fields) {
         if (fields) {
-            this.yes = fields.yes ? fields.yes : undefined;
-            this.abstained = fields.abstained ? fields.abstained : undefined;
-            this.no = fields.no ? fields.no : undefined;
+            this.label = fields.label;
+            this.votes = fields.votes;
         }
     }
 }
 
 /**
- * Borsh schema definition for votes account
+ * How much a single vote counts towards the tally, one of the variants below
+ */
+class VoteWeight {
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class OnePerWallet {
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class TokenWeight {
+    mint = new Uint8Array(32);
+
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class EscrowWeight {
+    mint = new Uint8Array(32);
+
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class MerkleAllowlist {
+    root = new Uint8Array(32);
+
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+/**
+ * Minimum participation for a poll to be decided, one of the variants below
+ */
+class Quorum {
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class NoQuorum {
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class AbsoluteQuorum {
+    votes = 0;
+
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class PercentQuorum {
+    percent = 0;
+
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+/**
+ * Pass thresholds and poll outcomes, stored as their variant index
+ */
+const Threshold = {SimpleMajority: 0, TwoThirds: 1, MajorityOfYesNo: 2};
+const Outcome = {Pending: 0, Passed: 1, Rejected: 2, QuorumNotMet: 3, Cancelled: 4};
+
+/**
+ * Lifecycle stage of a poll, stored as its variant index
+ */
+const ProposalState = {Draft: 0, Voting: 1, Succeeded: 2, Defeated: 3, Executed: 4, Cancelled: 5};
+
+/**
+ * What a single ballot of the poll expresses, one of the variants below
+ */
+class BallotType {
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class SingleChoice {
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class RankedChoice {
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class Approval {
+    min_selections = 0;
+    max_selections = 0;
+
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+class Quadratic {
+    voice_credits = 0;
+
+    constructor(fields) {
+        Object.assign(this, fields);
+    }
+}
+
+/**
+ * The state of a poll account managed by the vote program
+ */
+class Poll {
+    account_type = 0;
+    authority = new Uint8Array(32);
+    poll_id = 0;
+    title = '';
+    description_uri = '';
+    content_hash = new Uint8Array(32);
+    options = [];
+    start_ts = 0;
+    end_ts = 0;
+    vote_weight = new VoteWeight({onePerWallet: new OnePerWallet()});
+    voter_registry = null;
+    open_receipts = 0;
+    participating_weight = 0;
+    quorum = new Quorum({none: new NoQuorum()});
+    threshold = Threshold.SimpleMajority;
+    outcome = Outcome.Pending;
+    state = ProposalState.Voting;
+    ballot_type = new BallotType({singleChoice: new SingleChoice()});
+    ballot_count = 0;
+    reveal_end_ts = null;
+    unrevealed_commits = 0;
+    realm = null;
+    deposit = 0;
+    creator_escrow = null;
+    transaction_count = 0;
+    executed_transaction_count = 0;
+    is_closed = 0;
+    is_finalized = 0;
+
+    constructor(fields) {
+        if (fields) {
+            Object.assign(this, fields);
+        }
+    }
+}
+
+/**
+ * Borsh schema definition for poll accounts
  */
 const VoteSchema = new Map([
     [
-        VoteAccount,
+        VoteOption,
+        {
+            kind: 'struct',
+            fields: [
+                ['label', 'string'],
+                ['votes', 'u64'],
+            ]
+        }
+    ],
+    [
+        VoteWeight,
+        {
+            kind: 'enum',
+            field: 'enum',
+            values: [
+                ['onePerWallet', OnePerWallet],
+                ['token', TokenWeight],
+                ['escrow', EscrowWeight],
+                ['merkleAllowlist', MerkleAllowlist],
+            ]
+        }
+    ],
+    [
+        OnePerWallet,
+        {
+            kind: 'struct',
+            fields: []
+        }
+    ],
+    [
+        TokenWeight,
+        {
+            kind: 'struct',
+            fields: [
+                ['mint', [32]],
+            ]
+        }
+    ],
+    [
+        EscrowWeight,
+        {
+            kind: 'struct',
+            fields: [
+                ['mint', [32]],
+            ]
+        }
+    ],
+    [
+        MerkleAllowlist,
+        {
+            kind: 'struct',
+            fields: [
+                ['root', [32]],
+            ]
+        }
+    ],
+    [
+        Quorum,
+        {
+            kind: 'enum',
+            field: 'enum',
+            values: [
+                ['none', NoQuorum],
+                ['absolute', AbsoluteQuorum],
+                ['percent', PercentQuorum],
+            ]
+        }
+    ],
+    [
+        NoQuorum,
+        {
+            kind: 'struct',
+            fields: []
+        }
+    ],
+    [
+        AbsoluteQuorum,
+        {
+            kind: 'struct',
+            fields: [
+                ['votes', 'u64'],
+            ]
+        }
+    ],
+    [
+        PercentQuorum,
+        {
+            kind: 'struct',
+            fields: [
+                ['percent', 'u8'],
+            ]
+        }
+    ],
+    [
+        BallotType,
+        {
+            kind: 'enum',
+            field: 'enum',
+            values: [
+                ['singleChoice', SingleChoice],
+                ['rankedChoice', RankedChoice],
+                ['approval', Approval],
+                ['quadratic', Quadratic],
+            ]
+        }
+    ],
+    [
+        SingleChoice,
+        {
+            kind: 'struct',
+            fields: []
+        }
+    ],
+    [
+        RankedChoice,
+        {
+            kind: 'struct',
+            fields: []
+        }
+    ],
+    [
+        Approval,
         {
             kind: 'struct',
             fields: [
-                ['yes', 'u32'],
-                ['abstained', 'u32'],
-                ['no', 'u32'],
+                ['min_selections', 'u8'],
+                ['max_selections', 'u8'],
+            ]
+        }
+    ],
+    [
+        Quadratic,
+        {
+            kind: 'struct',
+            fields: [
+                ['voice_credits', 'u64'],
+            ]
+        }
+    ],
+    [
+        Poll,
+        {
+            kind: 'struct',
+            fields: [
+                ['account_type', 'u8'],
+                ['authority', [32]],
+                ['poll_id', 'u64'],
+                ['title', 'string'],
+                ['description_uri', 'string'],
+                ['content_hash', [32]],
+                ['options', [VoteOption]],
+                ['start_ts', 'u64'],
+                ['end_ts', 'u64'],
+                ['vote_weight', VoteWeight],
+                ['voter_registry', {kind: 'option', type: [32]}],
+                ['open_receipts', 'u64'],
+                ['participating_weight', 'u64'],
+                ['quorum', Quorum],
+                ['threshold', 'u8'],
+                ['outcome', 'u8'],
+                ['state', 'u8'],
+                ['ballot_type', BallotType],
+                ['ballot_count', 'u32'],
+                ['reveal_end_ts', {kind: 'option', type: 'u64'}],
+                ['unrevealed_commits', 'u64'],
+                ['realm', {kind: 'option', type: [32]}],
+                ['deposit', 'u64'],
+                ['creator_escrow', {kind: 'option', type: [32]}],
+                ['transaction_count', 'u16'],
+                ['executed_transaction_count', 'u16'],
+                ['is_closed', 'u8'],
+                ['is_finalized', 'u8'],
             ]
         }
     ],
 ]);
 
-const VOTE_SIZE = borsh.serialize(
-    VoteSchema,
-    new VoteAccount(),
-).length;
+/**
+ * Id of the poll created by this script, each creator can own many polls
+ */
+const POLL_ID = 0n;
+
+/**
+ * The poll created by this script
+ */
+const POLL_TITLE = 'Do you like this program?';
+const POLL_DESCRIPTION_URI = 'http://vote.hanmaster.ru/';
+const POLL_OPTIONS = ['yes', 'abstained', 'no'];
+const POLL_DURATION_SECONDS = 7n * 24n * 60n * 60n;
+
+const encodeU64 = (value) => {
+    const buffer = Buffer.alloc(8);
+    buffer.writeBigUInt64LE(value);
+    return buffer;
+}
+
+const encodeI64 = (value) => {
+    const buffer = Buffer.alloc(8);
+    buffer.writeBigInt64LE(value);
+    return buffer;
+}
+
+const encodeString = (value) => {
+    const bytes = Buffer.from(value, 'utf8');
+    const length = Buffer.alloc(4);
+    length.writeUInt32LE(bytes.length);
+    return Buffer.concat([length, bytes]);
+}
+
+const encodeStrings = (values) => {
+    const length = Buffer.alloc(4);
+    length.writeUInt32LE(values.length);
+    return Buffer.concat([length, ...values.map(encodeString)]);
+}
 
 const establishConnection = async () => {
     const rpcUrl = await getRpcUrl();
@@ -115,29 +470,44 @@ const createAccount = async () => {
 
     console.log(`payer: ${payer.publicKey.toBase58()}`)
 
-    // Derive the address (public key) of a greeting account from the program so that it's easy to find later.
-    const VOTE_SEED = 'vote';
-    votesPubkey = await web3.PublicKey.createWithSeed(
-        payer.publicKey,
-        VOTE_SEED,
+    // Derive the address (public key) of the poll account from the program so that it's easy to find later.
+    [votesPubkey] = await web3.PublicKey.findProgramAddress(
+        [Buffer.from('poll'), payer.publicKey.toBuffer(), encodeU64(POLL_ID)],
         programId,
     );
 
-    // Check if the greeting account has already been created
+    // Check if the poll account has already been created
     const votesAccount = await connection.getAccountInfo(votesPubkey);
     if (votesAccount === null) {
         console.log(`Creating votes account: ${votesPubkey.toBase58()}`);
-        const lamports = await connection.getMinimumBalanceForRentExemption(VOTE_SIZE);
 
+        // InitializePoll { poll_id, title, description_uri, content_hash, options, start_ts, end_ts, vote_weight, voter_registry, quorum, threshold, ballot_type, reveal_end_ts }
+        const startTs = BigInt(Math.floor(Date.now() / 1000));
+        const data = Buffer.concat([
+            Buffer.from([0]),
+            encodeU64(POLL_ID),
+            encodeString(POLL_TITLE),
+            encodeString(POLL_DESCRIPTION_URI),
+            Buffer.alloc(32),
+            encodeStrings(POLL_OPTIONS),
+            encodeI64(startTs),
+            encodeI64(startTs + POLL_DURATION_SECONDS),
+            borsh.serialize(VoteSchema, new VoteWeight({onePerWallet: new OnePerWallet()})),
+            Buffer.from([0]),
+            borsh.serialize(VoteSchema, new Quorum({none: new NoQuorum()})),
+            Buffer.from([Threshold.SimpleMajority]),
+            borsh.serialize(VoteSchema, new BallotType({singleChoice: new SingleChoice()})),
+            Buffer.from([0]),
+        ]);
         const transaction = new web3.Transaction().add(
-            web3.SystemProgram.createAccountWithSeed({
-                fromPubkey: payer.publicKey,
-                basePubkey: payer.publicKey,
-                seed: VOTE_SEED,
-                newAccountPubkey: votesPubkey,
-                lamports,
-                space: VOTE_SIZE,
+            new web3.TransactionInstruction({
+                keys: [
+                    {pubkey: votesPubkey, isSigner: false, isWritable: true},
+                    {pubkey: payer.publicKey, isSigner: true, isWritable: true},
+                    {pubkey: web3.SystemProgram.programId, isSigner: false, isWritable: false},
+                ],
                 programId,
+                data,
             }),
         );
         await web3.sendAndConfirmTransaction(connection, transaction, [payer]);
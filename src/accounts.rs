@@ -0,0 +1,903 @@
+//! Accounts expected by every instruction.
+//!
+//! Each instruction has a struct naming its accounts, built from the raw account list with
+//! `TryFrom<(&Pubkey, &[AccountInfo])>`. Building it checks signers, writability, owners,
+//! program ids and the program derived addresses that can be derived from other accounts,
+//! so the processor only works with accounts in the expected shape. Checks that depend on
+//! account data, like the poll's governance mint, stay with the processor.
+
+use std::slice::Iter;
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    msg,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_program,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    error::VoteError,
+    state::{find_escrow_address, find_escrow_vault_address, find_receipt_address},
+};
+
+pub struct InitializePollAccounts<'a, 'info> {
+    /// Checked against the poll id by the processor
+    pub poll: &'a AccountInfo<'info>,
+    pub creator: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    /// Registry polls only, checked against the poll's registry by the processor
+    pub voter_registry: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for InitializePollAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_writable(iter)?;
+        let creator = next_signer(iter, true)?;
+        let system_program = next_program(iter, &system_program::id())?;
+        let voter_registry = match iter.next() {
+            Some(voter_registry) => {
+                check_owner(voter_registry, program_id)?;
+                Some(voter_registry)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            poll,
+            creator,
+            system_program,
+            voter_registry,
+        })
+    }
+}
+
+pub struct CastVoteAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub voter: &'a AccountInfo<'info>,
+    pub receipt: &'a AccountInfo<'info>,
+    pub receipt_bump: u8,
+    pub system_program: &'a AccountInfo<'info>,
+    /// Token account or voter escrow, depending on how the poll weighs votes, followed by
+    /// the voter's membership in registry polls and the accounts of the delegators
+    pub remaining: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for CastVoteAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let voter = next_signer(iter, true)?;
+        let receipt = next_writable(iter)?;
+        let receipt_bump = check_receipt_address(program_id, poll, voter, receipt)?;
+        let system_program = next_program(iter, &system_program::id())?;
+
+        Ok(Self {
+            poll,
+            voter,
+            receipt,
+            receipt_bump,
+            system_program,
+            remaining: iter.as_slice(),
+        })
+    }
+}
+
+pub struct ClosePollAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for ClosePollAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let authority = next_signer(iter, false)?;
+
+        Ok(Self { poll, authority })
+    }
+}
+
+pub struct SignOffAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for SignOffAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let authority = next_signer(iter, false)?;
+
+        Ok(Self { poll, authority })
+    }
+}
+
+pub struct FinalizeAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    /// The electorate of percentage quorum polls, the creator of proposals with a deposit
+    /// and the creator escrow locked by proposals, checked against the poll by the processor
+    pub remaining: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for FinalizeAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+
+        Ok(Self {
+            poll,
+            remaining: iter.as_slice(),
+        })
+    }
+}
+
+pub struct DepositAccounts<'a, 'info> {
+    /// Not created yet on the first deposit
+    pub escrow: &'a AccountInfo<'info>,
+    pub escrow_bump: u8,
+    pub vault: &'a AccountInfo<'info>,
+    pub vault_bump: u8,
+    pub voter: &'a AccountInfo<'info>,
+    pub source: &'a AccountInfo<'info>,
+    pub mint: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for DepositAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let escrow = next_writable(iter)?;
+        let vault = next_writable(iter)?;
+        let voter = next_signer(iter, true)?;
+        let source = next_writable(iter)?;
+        let mint = next_account_info(iter)?;
+        check_owner(mint, &spl_token::id())?;
+        let system_program = next_program(iter, &system_program::id())?;
+        let token_program = next_program(iter, &spl_token::id())?;
+
+        let (escrow_address, escrow_bump) = find_escrow_address(program_id, mint.key, voter.key);
+        check_address(escrow, &escrow_address)?;
+        let (vault_address, vault_bump) =
+            find_escrow_vault_address(program_id, mint.key, voter.key);
+        check_address(vault, &vault_address)?;
+
+        Ok(Self {
+            escrow,
+            escrow_bump,
+            vault,
+            vault_bump,
+            voter,
+            source,
+            mint,
+            system_program,
+            token_program,
+        })
+    }
+}
+
+pub struct WithdrawAccounts<'a, 'info> {
+    pub escrow: &'a AccountInfo<'info>,
+    /// Checked against the escrow's mint by the processor
+    pub vault: &'a AccountInfo<'info>,
+    pub voter: &'a AccountInfo<'info>,
+    pub destination: &'a AccountInfo<'info>,
+    pub token_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for WithdrawAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let escrow = next_program_account(iter, program_id)?;
+        let vault = next_writable(iter)?;
+        let voter = next_signer(iter, false)?;
+        let destination = next_writable(iter)?;
+        let token_program = next_program(iter, &spl_token::id())?;
+
+        Ok(Self {
+            escrow,
+            vault,
+            voter,
+            destination,
+            token_program,
+        })
+    }
+}
+
+pub struct RelinquishVoteAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub voter: &'a AccountInfo<'info>,
+    pub receipt: &'a AccountInfo<'info>,
+    pub escrow: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for RelinquishVoteAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let voter = next_signer(iter, true)?;
+        let receipt = next_receipt(iter, program_id, poll, voter)?;
+        let escrow = next_program_account(iter, program_id)?;
+
+        Ok(Self {
+            poll,
+            voter,
+            receipt,
+            escrow,
+        })
+    }
+}
+
+pub struct ChangeVoteAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub voter: &'a AccountInfo<'info>,
+    pub receipt: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for ChangeVoteAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let voter = next_signer(iter, false)?;
+        let receipt = next_receipt(iter, program_id, poll, voter)?;
+
+        Ok(Self {
+            poll,
+            voter,
+            receipt,
+        })
+    }
+}
+
+pub struct RevealVoteAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub voter: &'a AccountInfo<'info>,
+    pub receipt: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for RevealVoteAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let voter = next_signer(iter, false)?;
+        let receipt = next_receipt(iter, program_id, poll, voter)?;
+
+        Ok(Self {
+            poll,
+            voter,
+            receipt,
+        })
+    }
+}
+
+pub struct RetractVoteAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub voter: &'a AccountInfo<'info>,
+    pub receipt: &'a AccountInfo<'info>,
+    /// The voter escrow in escrow weighted polls, then the delegators' accounts
+    pub remaining: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for RetractVoteAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let voter = next_signer(iter, true)?;
+        let receipt = next_receipt(iter, program_id, poll, voter)?;
+
+        Ok(Self {
+            poll,
+            voter,
+            receipt,
+            remaining: iter.as_slice(),
+        })
+    }
+}
+
+pub struct InitializeRegistryAccounts<'a, 'info> {
+    /// Checked against the registry id by the processor
+    pub registry: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for InitializeRegistryAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (_program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let registry = next_writable(iter)?;
+        let authority = next_signer(iter, true)?;
+        let system_program = next_program(iter, &system_program::id())?;
+
+        Ok(Self {
+            registry,
+            authority,
+            system_program,
+        })
+    }
+}
+
+pub struct AddMemberAccounts<'a, 'info> {
+    pub registry: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    /// Checked against the member by the processor
+    pub membership: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for AddMemberAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let registry = next_program_account(iter, program_id)?;
+        let authority = next_signer(iter, true)?;
+        let membership = next_writable(iter)?;
+        let system_program = next_program(iter, &system_program::id())?;
+
+        Ok(Self {
+            registry,
+            authority,
+            membership,
+            system_program,
+        })
+    }
+}
+
+pub struct RemoveMemberAccounts<'a, 'info> {
+    pub registry: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    /// Checked against the member by the processor
+    pub membership: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for RemoveMemberAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let registry = next_program_account(iter, program_id)?;
+        let authority = next_signer(iter, true)?;
+        let membership = next_writable(iter)?;
+
+        Ok(Self {
+            registry,
+            authority,
+            membership,
+        })
+    }
+}
+
+pub struct ClosePollAccountAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub recipient: &'a AccountInfo<'info>,
+    /// Ranked choice polls only, checked against the poll by the processor
+    pub ranked_tally: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for ClosePollAccountAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let authority = next_signer(iter, false)?;
+        let recipient = next_writable(iter)?;
+        let ranked_tally = iter.next();
+
+        Ok(Self {
+            poll,
+            authority,
+            recipient,
+            ranked_tally,
+        })
+    }
+}
+
+pub struct CloseReceiptAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub receipt: &'a AccountInfo<'info>,
+    pub voter: &'a AccountInfo<'info>,
+    /// Escrow polls only, checked against the voter by the processor
+    pub escrow: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for CloseReceiptAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let receipt = next_writable(iter)?;
+        let voter = next_writable(iter)?;
+        check_receipt_address(program_id, poll, voter, receipt)?;
+        if receipt.owner != program_id {
+            return Err(VoteError::NotVoted.into());
+        }
+        let escrow = iter.next();
+
+        Ok(Self {
+            poll,
+            receipt,
+            voter,
+            escrow,
+        })
+    }
+}
+
+pub struct InsertTransactionAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    /// Checked against the index by the processor
+    pub proposal_transaction: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for InsertTransactionAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let authority = next_signer(iter, true)?;
+        let proposal_transaction = next_writable(iter)?;
+        let system_program = next_program(iter, &system_program::id())?;
+
+        Ok(Self {
+            poll,
+            authority,
+            proposal_transaction,
+            system_program,
+        })
+    }
+}
+
+pub struct ExecuteProposalAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub proposal_transaction: &'a AccountInfo<'info>,
+    /// Checked against the poll authority by the processor
+    pub treasury: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for ExecuteProposalAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let proposal_transaction = next_program_account(iter, program_id)?;
+        let treasury = next_writable(iter)?;
+
+        Ok(Self {
+            poll,
+            proposal_transaction,
+            treasury,
+        })
+    }
+}
+
+pub struct CloseProposalTransactionAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub proposal_transaction: &'a AccountInfo<'info>,
+    /// Checked against the poll authority by the processor
+    pub authority: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for CloseProposalTransactionAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let proposal_transaction = next_program_account(iter, program_id)?;
+        let authority = next_writable(iter)?;
+
+        Ok(Self {
+            poll,
+            proposal_transaction,
+            authority,
+        })
+    }
+}
+
+pub struct CloseBallotsAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub recipient: &'a AccountInfo<'info>,
+    /// Checked against the poll by the processor
+    pub ranked_tally: &'a AccountInfo<'info>,
+    /// Checked against the chunk indexes by the processor
+    pub ballot_chunks: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for CloseBallotsAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let authority = next_signer(iter, false)?;
+        let recipient = next_writable(iter)?;
+        let ranked_tally = next_writable(iter)?;
+
+        Ok(Self {
+            poll,
+            authority,
+            recipient,
+            ranked_tally,
+            ballot_chunks: iter.as_slice(),
+        })
+    }
+}
+
+pub struct TallyAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    /// Checked against the poll by the processor
+    pub ranked_tally: &'a AccountInfo<'info>,
+    pub payer: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    /// Checked against the chunk indexes by the processor
+    pub ballot_chunks: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for TallyAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_account_info(iter)?;
+        check_owner(poll, program_id)?;
+        let ranked_tally = next_writable(iter)?;
+        let payer = next_signer(iter, true)?;
+        let system_program = next_program(iter, &system_program::id())?;
+
+        Ok(Self {
+            poll,
+            ranked_tally,
+            payer,
+            system_program,
+            ballot_chunks: iter.as_slice(),
+        })
+    }
+}
+
+pub struct DelegateAccounts<'a, 'info> {
+    pub delegator: &'a AccountInfo<'info>,
+    /// Checked against the scope by the processor
+    pub delegation: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for DelegateAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (_program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let delegator = next_signer(iter, true)?;
+        let delegation = next_writable(iter)?;
+        let system_program = next_program(iter, &system_program::id())?;
+
+        Ok(Self {
+            delegator,
+            delegation,
+            system_program,
+        })
+    }
+}
+
+pub struct RevokeDelegationAccounts<'a, 'info> {
+    pub delegator: &'a AccountInfo<'info>,
+    pub delegation: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for RevokeDelegationAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let delegator = next_signer(iter, true)?;
+        let delegation = next_program_account(iter, program_id)?;
+
+        Ok(Self {
+            delegator,
+            delegation,
+        })
+    }
+}
+
+pub struct CreateRealmAccounts<'a, 'info> {
+    /// Checked against the name by the processor
+    pub realm: &'a AccountInfo<'info>,
+    pub authority: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])> for CreateRealmAccounts<'a, 'info> {
+    type Error = ProgramError;
+
+    fn try_from(
+        (_program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let realm = next_writable(iter)?;
+        let authority = next_signer(iter, true)?;
+        let system_program = next_program(iter, &system_program::id())?;
+
+        Ok(Self {
+            realm,
+            authority,
+            system_program,
+        })
+    }
+}
+
+pub struct CreateProposalAccounts<'a, 'info> {
+    pub realm: &'a AccountInfo<'info>,
+    /// Checked against the proposal index by the processor
+    pub poll: &'a AccountInfo<'info>,
+    pub creator: &'a AccountInfo<'info>,
+    pub system_program: &'a AccountInfo<'info>,
+    /// Checked against the creator by the processor
+    pub creator_escrow: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for CreateProposalAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let realm = next_program_account(iter, program_id)?;
+        let poll = next_writable(iter)?;
+        let creator = next_signer(iter, true)?;
+        let system_program = next_program(iter, &system_program::id())?;
+        let creator_escrow = iter.next();
+
+        Ok(Self {
+            realm,
+            poll,
+            creator,
+            system_program,
+            creator_escrow,
+        })
+    }
+}
+
+pub struct CancelProposalAccounts<'a, 'info> {
+    pub poll: &'a AccountInfo<'info>,
+    pub signer: &'a AccountInfo<'info>,
+    /// The realm and its treasury when the realm authority cancels, then the creator escrow
+    /// locked by the proposal
+    pub remaining: &'a [AccountInfo<'info>],
+}
+
+impl<'a, 'info> TryFrom<(&'a Pubkey, &'a [AccountInfo<'info>])>
+    for CancelProposalAccounts<'a, 'info>
+{
+    type Error = ProgramError;
+
+    fn try_from(
+        (program_id, accounts): (&'a Pubkey, &'a [AccountInfo<'info>]),
+    ) -> Result<Self, Self::Error> {
+        let iter = &mut accounts.iter();
+        let poll = next_program_account(iter, program_id)?;
+        let signer = next_signer(iter, true)?;
+
+        Ok(Self {
+            poll,
+            signer,
+            remaining: iter.as_slice(),
+        })
+    }
+}
+
+/// Next account, which must be writable.
+fn next_writable<'a, 'info>(
+    iter: &mut Iter<'a, AccountInfo<'info>>,
+) -> Result<&'a AccountInfo<'info>, ProgramError> {
+    let account = next_account_info(iter)?;
+    check_writable(account)?;
+    Ok(account)
+}
+
+/// Next account, which must sign and, if it pays or is refunded, be writable.
+fn next_signer<'a, 'info>(
+    iter: &mut Iter<'a, AccountInfo<'info>>,
+    is_writable: bool,
+) -> Result<&'a AccountInfo<'info>, ProgramError> {
+    let account = next_account_info(iter)?;
+    if !account.is_signer {
+        msg!("Account {} must sign the instruction", account.key);
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if is_writable {
+        check_writable(account)?;
+    }
+    Ok(account)
+}
+
+/// Next account, a writable, rent exempt account owned by the program.
+fn next_program_account<'a, 'info>(
+    iter: &mut Iter<'a, AccountInfo<'info>>,
+    program_id: &Pubkey,
+) -> Result<&'a AccountInfo<'info>, ProgramError> {
+    let account = next_writable(iter)?;
+    check_owner(account, program_id)?;
+    check_rent_exempt(account)?;
+    Ok(account)
+}
+
+/// Next account, the given program.
+fn next_program<'a, 'info>(
+    iter: &mut Iter<'a, AccountInfo<'info>>,
+    program_id: &Pubkey,
+) -> Result<&'a AccountInfo<'info>, ProgramError> {
+    let account = next_account_info(iter)?;
+    if account.key != program_id {
+        msg!("Expected program {}, got {}", program_id, account.key);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(account)
+}
+
+/// Next account, the existing vote receipt of (poll, voter).
+fn next_receipt<'a, 'info>(
+    iter: &mut Iter<'a, AccountInfo<'info>>,
+    program_id: &Pubkey,
+    poll: &AccountInfo,
+    voter: &AccountInfo,
+) -> Result<&'a AccountInfo<'info>, ProgramError> {
+    let receipt = next_writable(iter)?;
+    check_receipt_address(program_id, poll, voter, receipt)?;
+    if receipt.owner != program_id {
+        return Err(VoteError::NotVoted.into());
+    }
+    Ok(receipt)
+}
+
+fn check_receipt_address(
+    program_id: &Pubkey,
+    poll: &AccountInfo,
+    voter: &AccountInfo,
+    receipt: &AccountInfo,
+) -> Result<u8, ProgramError> {
+    let (receipt_address, bump) = find_receipt_address(program_id, poll.key, voter.key);
+    check_address(receipt, &receipt_address)?;
+    Ok(bump)
+}
+
+pub fn check_writable(account: &AccountInfo) -> Result<(), ProgramError> {
+    if !account.is_writable {
+        msg!("Account {} must be writable", account.key);
+        return Err(VoteError::AccountNotWritable.into());
+    }
+    Ok(())
+}
+
+pub fn check_owner(account: &AccountInfo, owner: &Pubkey) -> Result<(), ProgramError> {
+    if account.owner != owner {
+        msg!("Account {} must be owned by {}", account.key, owner);
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+/// Checks `account` is the program derived address it is expected to be.
+pub fn check_address(account: &AccountInfo, address: &Pubkey) -> Result<(), ProgramError> {
+    if account.key != address {
+        msg!(
+            "Account {} does not match its derived address {}",
+            account.key,
+            address
+        );
+        return Err(ProgramError::InvalidSeeds);
+    }
+    Ok(())
+}
+
+pub fn check_rent_exempt(account: &AccountInfo) -> Result<(), ProgramError> {
+    if !Rent::get()?.is_exempt(account.lamports(), account.data_len()) {
+        msg!("Account {} is not rent exempt", account.key);
+        return Err(ProgramError::AccountNotRentExempt);
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use solana_program::{
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
+};
+use thiserror::Error;
+
+/// Errors that may be returned by the vote program.
+///
+/// The discriminant of each variant is the `ProgramError::Custom` code seen by clients,
+/// so variants are only ever appended.
+#[derive(Clone, Debug, Eq, Error, FromPrimitive, PartialEq)]
+pub enum VoteError {
+    #[error("Unknown vote option")]
+    UnknownVoteOption,
+    #[error("Poll is closed")]
+    PollClosed,
+    #[error("Voter has already voted in this poll")]
+    AlreadyVoted,
+    #[error("Vote counter overflow")]
+    CounterOverflow,
+    #[error("Poll account is not initialized")]
+    NotInitialized,
+    #[error("Poll must have between 2 and 20 options")]
+    InvalidOptionCount,
+    #[error("Option label is too long")]
+    OptionLabelTooLong,
+    #[error("Poll title is too long")]
+    TitleTooLong,
+    #[error("Poll description URI is too long")]
+    DescriptionUriTooLong,
+    #[error("Signer is not the poll authority")]
+    InvalidAuthority,
+    #[error("Voting window must end after it starts")]
+    InvalidVotingWindow,
+    #[error("Voting has not started yet")]
+    NotStarted,
+    #[error("Voting has ended")]
+    Ended,
+    #[error("Voting has not ended yet")]
+    VotingNotEnded,
+    #[error("Poll is already finalized")]
+    AlreadyFinalized,
+    /// Deprecated, no longer returned
+    #[error("Deprecated error")]
+    DeprecatedLegacyPollLayout,
+    /// Deprecated, no longer returned
+    #[error("Deprecated error")]
+    DeprecatedAlreadyMigrated,
+    #[error("Token account mint is not the poll governance mint")]
+    TokenMintMismatch,
+    #[error("Token account is not owned by the voter")]
+    TokenOwnerMismatch,
+    #[error("Voter has no vote weight")]
+    NoVoteWeight,
+    #[error("Voter escrow does not belong to the voter and governance mint")]
+    InvalidVoterEscrow,
+    #[error("Escrowed tokens back votes that have not been relinquished")]
+    EscrowLocked,
+    #[error("Withdrawal exceeds the escrowed amount")]
+    InsufficientEscrow,
+    #[error("Vote is not weighted by a voter escrow")]
+    NotEscrowVote,
+    #[error("Voter has not voted in this poll")]
+    NotVoted,
+    #[error("Account must be writable")]
+    AccountNotWritable,
+    #[error("Voter is not a member of the poll's voter registry")]
+    NotRegistryMember,
+    #[error("Wallet is already a member of the voter registry")]
+    AlreadyRegistryMember,
+    #[error("Poll requires a Merkle proof of the voter's weight")]
+    MerkleProofRequired,
+    #[error("Merkle proof does not match the poll's allowlist")]
+    InvalidMerkleProof,
+    #[error("Poll must be finalized first")]
+    NotFinalized,
+    #[error("Account has been closed")]
+    AccountClosed,
+    #[error("Vote receipts must be closed or relinquished first")]
+    ReceiptsOutstanding,
+    /// Deprecated, no longer returned
+    #[error("Deprecated error")]
+    DeprecatedPollNotClosed,
+    #[error("Quorum can't be applied to the poll")]
+    InvalidQuorum,
+    #[error("Transactions can only be attached before voting starts")]
+    VotingStarted,
+    #[error("Poll has not passed")]
+    ProposalNotPassed,
+    #[error("Hold up time has not elapsed yet")]
+    HoldUpTimeNotElapsed,
+    #[error("Proposal transaction was already executed")]
+    AlreadyExecuted,
+    #[error("Ballot type not supported by the poll")]
+    UnsupportedBallotType,
+    #[error("Ranking must list each option at most once")]
+    InvalidRanking,
+    #[error("Tally is already complete")]
+    TallyComplete,
+    #[error("Option selected more than once")]
+    DuplicateSelection,
+    #[error("Number of selected options out of the poll's bounds")]
+    InvalidSelectionCount,
+    #[error("Voice credits must buy at least one vote per option")]
+    InvalidAllocation,
+    #[error("Not enough voice credits")]
+    InsufficientVoiceCredits,
+    #[error("Poll is not in its reveal phase")]
+    RevealNotOpen,
+    #[error("Revealed vote does not match the commitment")]
+    InvalidReveal,
+    #[error("Vote has no commitment to reveal")]
+    NotCommitted,
+    #[error("Delegation does not cover this vote")]
+    InvalidDelegation,
+    #[error("Vote was cast by a delegate")]
+    VoteDelegated,
+    #[error("Realm name is empty or too long")]
+    InvalidRealmName,
+    #[error("Not enough governance tokens to create a proposal")]
+    InsufficientProposalWeight,
+    #[error("Not allowed in the current proposal state")]
+    InvalidProposalState,
+    #[error("Proposal transactions must be closed first")]
+    TransactionsOutstanding,
+    #[error("Ranked ballots must be tallied first")]
+    TallyNotComplete,
+    #[error("Threshold can't be applied to the poll")]
+    InvalidThreshold,
+    #[error("Ballot chunks and the ranked tally must be closed first")]
+    BallotsOutstanding,
+    #[error("Proposal transaction can no longer be executed")]
+    TransactionExpired,
+    #[error("Delegated votes must be retracted along with the delegators' receipts")]
+    DelegatorsOutstanding,
+}
+
+impl From<VoteError> for ProgramError {
+    fn from(e: VoteError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for VoteError {
+    fn type_of() -> &'static str {
+        "VoteError"
+    }
+}
+
+impl PrintProgramError for VoteError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        msg!("Error: {}", self);
+    }
+}
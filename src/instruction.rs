@@ -0,0 +1,1237 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::state::{
+    find_ballot_chunk_address, find_delegation_address, find_escrow_address,
+    find_escrow_vault_address, find_member_address, find_poll_address,
+    find_proposal_transaction_address, find_ranked_tally_address, find_realm_address,
+    find_receipt_address, find_registry_address, find_treasury_address, BallotType,
+    DelegationScope, InstructionData, Quorum, Threshold, VoiceCreditAllocation, VoteWeight,
+    BALLOTS_PER_CHUNK,
+};
+
+/// Instructions supported by the vote program.
+///
+/// Instructions are Borsh encoded: the first byte is the variant index followed by the
+/// variant fields. Variants are only ever appended so existing clients keep working.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+#[allow(clippy::large_enum_variant)]
+pub enum VoteInstruction {
+    /// Creates a rent exempt poll account at the program derived address of
+    /// (creator, poll id) with one zeroed counter per option label. Votes are accepted
+    /// from `start_ts` until `end_ts` and weighted by `vote_weight`. The creator becomes
+    /// the poll authority. With a `voter_registry` only its members may vote, the registry
+    /// must be managed by the creator. `quorum` and `threshold` decide the outcome stored
+    /// on finalization, a percentage quorum needs a governance mint or a voter registry.
+    /// Only single choice polls can use a Merkle allowlist. With a `reveal_end_ts` votes
+    /// are secret: committed until `end_ts` and revealed until `reveal_end_ts`, this needs
+    /// single choice ballots and doesn't work with a Merkle allowlist.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account, program derived address of (creator, poll id)
+    /// 1. `[writable, signer]` The poll creator, pays for the poll account
+    /// 2. `[]` The system program
+    /// 3. `[]` Registry polls only: the voter registry
+    InitializePoll {
+        poll_id: u64,
+        title: String,
+        description_uri: String,
+        content_hash: [u8; 32],
+        options: Vec<String>,
+        start_ts: i64,
+        end_ts: i64,
+        vote_weight: VoteWeight,
+        voter_registry: Option<Pubkey>,
+        quorum: Quorum,
+        threshold: Threshold,
+        ballot_type: BallotType,
+        reveal_end_ts: Option<i64>,
+    },
+
+    /// Casts a vote for the option at the given index, only within the voting window.
+    ///
+    /// Each wallet may vote once per poll, the vote receipt is created on the first vote.
+    /// In token weighted polls the vote counts the balance of the voter's token account,
+    /// in escrow weighted polls the tokens locked in the voter escrow.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable, signer]` The voter, pays for the vote receipt
+    /// 2. `[writable]` The vote receipt, program derived address of (poll, voter)
+    /// 3. `[]` The system program
+    /// 4. `[]` Token weighted polls only: the voter's token account of the governance mint
+    /// 4. `[writable]` Escrow weighted polls only: the voter escrow
+    /// 5. `[]` Registry polls only: the voter's membership, program derived address of
+    ///    (registry, voter). Index 4 in one per wallet polls.
+    ///
+    /// A delegate votes with the summed weight of its delegators by appending, per
+    /// delegator, the accounts returned by `delegator_accounts`:
+    /// 0. `[]` The delegation
+    /// 1. `[writable]` The delegator's vote receipt, keeping the delegator from voting
+    /// 2. `[]`/`[writable]` Token and escrow weighted polls only: the delegator's token
+    ///    account or voter escrow
+    /// 3. `[]` Registry polls only: the delegator's membership
+    CastVote { option: u8 },
+
+    /// Stops the poll from accepting further votes and cancels it, a poll cut short never
+    /// passes. Only before its tally is final, an ended poll is finalized instead. Realm
+    /// proposals can't be closed, they are cancelled with `CancelProposal`.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[signer]` The poll authority
+    ClosePoll,
+
+    /// Freezes the results once the voting window has passed and stores the poll outcome,
+    /// callable by anyone. The poll succeeds if it passed and is defeated otherwise.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[]` Percentage quorum polls only: the governance mint, or the voter registry in
+    ///    one per wallet polls
+    /// 2. `[writable]` Proposals with a deposit only: the creator, receiving the deposit.
+    ///    Index 1 without a percentage quorum.
+    /// 3. `[writable]` Proposals with a locked creator escrow only: the creator's escrow,
+    ///    unlocked for withdrawal
+    /// 4. `[]` Ranked choice polls only: the completed ranked tally, following whichever of
+    ///    the accounts above apply. Decides the outcome instead of the first preferences.
+    Finalize,
+
+    /// Deprecated, always fails with `InvalidInstructionData`
+    DeprecatedMigratePoll,
+
+    /// Locks governance tokens in the voter escrow, creating the escrow and its token
+    /// vault on the first deposit.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The voter escrow, program derived address of (mint, voter)
+    /// 1. `[writable]` The escrow vault, program derived address of (mint, voter)
+    /// 2. `[writable, signer]` The voter, pays for the escrow accounts
+    /// 3. `[writable]` The voter's token account to take the tokens from
+    /// 4. `[]` The governance mint
+    /// 5. `[]` The system program
+    /// 6. `[]` The token program
+    Deposit { amount: u64 },
+
+    /// Returns escrowed tokens to the voter. Only allowed once every vote cast with the
+    /// escrow has been relinquished.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The voter escrow
+    /// 1. `[writable]` The escrow vault
+    /// 2. `[signer]` The voter
+    /// 3. `[writable]` The token account to send the tokens to
+    /// 4. `[]` The token program
+    Withdraw { amount: u64 },
+
+    /// Releases the escrowed tokens backing a vote and closes the vote receipt, refunding
+    /// its rent to the voter. While voting is still open the vote is also removed from
+    /// the tally, afterwards the tally is kept. A delegate's vote has to be retracted
+    /// instead while voting is open, which releases the delegators as well.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable, signer]` The voter
+    /// 2. `[writable]` The vote receipt
+    /// 3. `[writable]` The voter escrow
+    RelinquishVote,
+
+    /// Moves the voter's vote to another option, only within the voting window. The vote
+    /// keeps the weight it was cast with.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[signer]` The voter
+    /// 2. `[writable]` The vote receipt
+    ChangeVote { option: u8 },
+
+    /// Takes the voter's vote out of the tally and closes the vote receipt, refunding its
+    /// rent to the voter. Only within the voting window.
+    ///
+    /// A delegate retracting takes the delegated weight out along with their own, and
+    /// closes the receipts of every delegator the vote was cast for so they can vote again.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable, signer]` The voter
+    /// 2. `[writable]` The vote receipt
+    /// 3. `[writable]` Escrow weighted polls only: the voter escrow
+    /// 4. .. Per delegator, the delegator's vote receipt followed in escrow weighted polls
+    ///    by the delegator's escrow
+    RetractVote,
+
+    /// Creates an empty voter registry at the program derived address of
+    /// (authority, registry id).
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The voter registry
+    /// 1. `[writable, signer]` The registry authority, pays for the registry
+    /// 2. `[]` The system program
+    InitializeRegistry { registry_id: u64 },
+
+    /// Adds a wallet to the voter registry by creating its membership account.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The voter registry
+    /// 1. `[writable, signer]` The registry authority, pays for the membership
+    /// 2. `[writable]` The membership, program derived address of (registry, member)
+    /// 3. `[]` The system program
+    AddMember { member: Pubkey },
+
+    /// Removes a wallet from the voter registry, refunding the membership rent to the
+    /// authority. Votes already cast by the member are kept.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The voter registry
+    /// 1. `[writable, signer]` The registry authority
+    /// 2. `[writable]` The membership
+    RemoveMember { member: Pubkey },
+
+    /// Casts a vote in a Merkle allowlist poll, proving the voter's leaf of
+    /// (voter, weight) is part of the poll's tree. Otherwise the same as `CastVote`.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable, signer]` The voter, pays for the vote receipt
+    /// 2. `[writable]` The vote receipt, program derived address of (poll, voter)
+    /// 3. `[]` The system program
+    /// 4. `[]` Registry polls only: the voter's membership
+    CastVoteWithProof {
+        option: u8,
+        weight: u64,
+        proof: Vec<[u8; 32]>,
+    },
+
+    /// Closes a finalized poll, sending its rent to the recipient. The data is zeroed and
+    /// the account left as a closed tombstone, so it can never be used again with stale
+    /// data. Only allowed once every vote receipt has been closed, or relinquished in
+    /// escrow weighted polls, and every proposal transaction has been closed, so none can
+    /// be used against a poll re-created at the address.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[signer]` The poll authority
+    /// 2. `[writable]` The recipient of the rent
+    /// 3. `[]` Ranked choice polls only: the ranked tally, which must be closed already
+    ClosePollAccount,
+
+    /// Closes a vote receipt of a finalized poll, refunding its rent to the voter. Callable
+    /// by anyone. In escrow polls this also releases the escrowed tokens backing the vote.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The finalized poll account
+    /// 1. `[writable]` The vote receipt
+    /// 2. `[writable]` The voter
+    /// 3. `[writable]` Escrow polls only: the voter escrow
+    CloseReceipt,
+
+    /// Attaches instructions to a poll, executed on behalf of the treasury of the poll
+    /// authority once the poll has passed and `hold_up_time` seconds passed after voting
+    /// ended. Only allowed in drafts and before voting starts, so voters know what they
+    /// vote on.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable, signer]` The poll authority, pays for the proposal transaction
+    /// 2. `[writable]` The proposal transaction, program derived address of (poll, index)
+    /// 3. `[]` The system program
+    InsertTransaction {
+        index: u16,
+        hold_up_time: u32,
+        instructions: Vec<InstructionData>,
+    },
+
+    /// Executes the instructions of a proposal transaction, signed by the treasury.
+    /// Callable by anyone once the poll has passed and the hold up time elapsed, until
+    /// the execution grace period runs out.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable]` The proposal transaction
+    /// 2. `[writable]` The treasury, program derived address of the poll authority or, for
+    ///    proposals, the realm
+    /// 3. .. Every program and account the instructions are invoked with
+    ExecuteProposal,
+
+    /// Casts a ranked ballot in a ranked choice poll, listing options from most to least
+    /// preferred. Options left out are never counted for the ballot. The ballot is stored
+    /// in the current ballot chunk and counts as a vote for its first preference until
+    /// the poll is tallied. Otherwise the same as `CastVote`, the ballot can't be changed.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable, signer]` The voter, pays for the vote receipt and new ballot chunks
+    /// 2. `[writable]` The vote receipt, program derived address of (poll, voter)
+    /// 3. `[]` The system program
+    /// 4. `[writable]` The current ballot chunk, program derived address of
+    ///    (poll, ballot count / `BALLOTS_PER_CHUNK`)
+    /// 5. .. The weight source and membership, as for `CastVote`
+    CastRankedVote { ranking: Vec<u8> },
+
+    /// Advances the instant-runoff count of a ranked choice poll once voting is over,
+    /// callable by anyone. Each round goes through every ballot chunk in order, the count
+    /// continues with the chunks passed and resumes in the next transaction where this
+    /// one stopped.
+    ///
+    /// Accounts expected:
+    /// 0. `[]` The poll account
+    /// 1. `[writable]` The ranked tally, program derived address of the poll
+    /// 2. `[writable, signer]` The payer of the ranked tally, on the first call
+    /// 3. `[]` The system program
+    /// 4. .. Ballot chunks, starting with the chunk the count continues with
+    Tally,
+
+    /// Casts a vote for every listed option in an approval poll, within the poll's
+    /// minimum and maximum number of selections. Otherwise the same as `CastVote`, the
+    /// vote can be retracted but not changed.
+    ///
+    /// Accounts expected: the same as `CastVote`
+    CastApprovalVote { options: Vec<u8> },
+
+    /// Spends voice credits on any number of options of a quadratic poll, each option
+    /// gets the square root of its credits as votes. The credits spent may not exceed the
+    /// voter's budget. Otherwise the same as `CastVote`, the vote can be retracted but not
+    /// changed.
+    ///
+    /// Accounts expected: the same as `CastVote`
+    CastQuadraticVote {
+        allocations: Vec<VoiceCreditAllocation>,
+    },
+
+    /// Commits to a secret vote in a commit-reveal poll while voting is open, see
+    /// `vote_commitment`. The vote weight is taken at commit time, the vote only counts
+    /// once revealed. Otherwise the same as `CastVote`, the commitment can be retracted
+    /// but not changed.
+    ///
+    /// Accounts expected: the same as `CastVote`
+    CommitVote { commitment: [u8; 32] },
+
+    /// Reveals a committed vote after voting ended and before `reveal_end_ts`, adding it
+    /// to the tally.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[signer]` The voter
+    /// 2. `[writable]` The vote receipt
+    RevealVote { option: u8, salt: [u8; 32] },
+
+    /// Hands the delegator's vote weight in the polls of `scope` to `delegate`, replacing
+    /// the delegate of an existing delegation with the same scope.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The delegator, pays for the delegation
+    /// 1. `[writable]` The delegation, program derived address of (delegator, scope)
+    /// 2. `[]` The system program
+    Delegate {
+        delegate: Pubkey,
+        scope: DelegationScope,
+    },
+
+    /// Closes a delegation, refunding its rent to the delegator. Votes the delegate
+    /// already cast with the delegator's weight stand.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable, signer]` The delegator
+    /// 1. `[writable]` The delegation
+    RevokeDelegation,
+
+    /// Creates a realm at the program derived address of its name, the signer becoming
+    /// its authority.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The realm
+    /// 1. `[writable, signer]` The realm authority, pays for the realm
+    /// 2. `[]` The system program
+    CreateRealm {
+        name: String,
+        governance_mint: Pubkey,
+        voting_duration: u32,
+        quorum: Quorum,
+        threshold: Threshold,
+        min_proposal_weight: u64,
+        proposal_deposit: u64,
+    },
+
+    /// Creates the next proposal of a realm, a poll at the program derived address of
+    /// (realm, proposal index). Votes are weighted by escrowed governance tokens and
+    /// accepted for the realm's voting duration from `start_ts`, the quorum and threshold
+    /// are the realm's. The creator becomes the poll authority, needs the realm's minimum
+    /// of escrowed governance tokens and locks the realm's proposal deposit in the poll.
+    /// The escrowed tokens can't be withdrawn until the proposal is finalized or cancelled.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The realm
+    /// 1. `[writable]` The poll account
+    /// 2. `[writable, signer]` The creator, pays for the poll account and the deposit
+    /// 3. `[]` The system program
+    /// 4. `[writable]` Realms with a minimum proposal weight only: the creator's voter escrow
+    CreateProposal {
+        title: String,
+        description_uri: String,
+        content_hash: [u8; 32],
+        options: Vec<String>,
+        start_ts: i64,
+        ballot_type: BallotType,
+    },
+
+    /// Cancels a draft, or a poll before its tally is final. The creator gets the deposit
+    /// back, the realm authority cancelling someone else's proposal as spam forfeits the
+    /// deposit to the realm treasury.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable, signer]` The poll authority, or the realm authority of a proposal
+    /// 2. `[]` Realm authority only: the realm
+    /// 3. `[writable]` Realm authority only: the realm treasury
+    /// 4. `[writable]` Proposals with a locked creator escrow only: the creator's escrow,
+    ///    following whichever of the accounts above apply
+    CancelProposal,
+
+    /// Opens a draft proposal for voting. Signing off after `start_ts` moves the voting
+    /// window, so voting still lasts the realm's full voting duration.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[signer]` The poll authority
+    SignOff,
+
+    /// Closes an executed proposal transaction, one of a poll that was defeated or
+    /// cancelled, or one that expired unexecuted, refunding its rent to the poll authority.
+    /// Callable by anyone. Once none of its transactions is left to execute, a passed poll
+    /// counts as executed, or as defeated if none of them ran.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[writable]` The proposal transaction
+    /// 2. `[writable]` The poll authority
+    CloseProposalTransaction,
+
+    /// Closes the ranked tally and ballot chunks of a finalized ranked choice poll, sending
+    /// their rent to the recipient. Chunks are closed from the last one down, as many as
+    /// are passed.
+    ///
+    /// Accounts expected:
+    /// 0. `[writable]` The poll account
+    /// 1. `[signer]` The poll authority
+    /// 2. `[writable]` The recipient of the rent
+    /// 3. `[writable]` The ranked tally, closed if it exists
+    /// 4. .. `[writable]` Ballot chunks, starting with the last one still open
+    CloseBallots,
+}
+
+impl VoteInstruction {
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        Self::try_from_slice(input).map_err(|_| ProgramError::InvalidInstructionData)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn initialize_poll(
+    program_id: &Pubkey,
+    creator: &Pubkey,
+    poll_id: u64,
+    title: String,
+    description_uri: String,
+    content_hash: [u8; 32],
+    options: Vec<String>,
+    start_ts: i64,
+    end_ts: i64,
+    vote_weight: VoteWeight,
+    voter_registry: Option<Pubkey>,
+    quorum: Quorum,
+    threshold: Threshold,
+    ballot_type: BallotType,
+    reveal_end_ts: Option<i64>,
+) -> Instruction {
+    let (poll, _) = find_poll_address(program_id, creator, poll_id);
+    let mut accounts = vec![
+        AccountMeta::new(poll, false),
+        AccountMeta::new(*creator, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(voter_registry) = voter_registry {
+        accounts.push(AccountMeta::new_readonly(voter_registry, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::InitializePoll {
+            poll_id,
+            title,
+            description_uri,
+            content_hash,
+            options,
+            start_ts,
+            end_ts,
+            vote_weight,
+            voter_registry,
+            quorum,
+            threshold,
+            ballot_type,
+            reveal_end_ts,
+        },
+        accounts,
+    )
+}
+
+pub fn cast_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    voter_token_account: Option<&Pubkey>,
+    voter_registry: Option<&Pubkey>,
+    option: u8,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*voter, true),
+        AccountMeta::new(receipt, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(voter_token_account) = voter_token_account {
+        accounts.push(AccountMeta::new_readonly(*voter_token_account, false));
+    }
+    if let Some(voter_registry) = voter_registry {
+        let (membership, _) = find_member_address(program_id, voter_registry, voter);
+        accounts.push(AccountMeta::new_readonly(membership, false));
+    }
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::CastVote { option }, accounts)
+}
+
+pub fn cast_escrow_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    mint: &Pubkey,
+    voter_registry: Option<&Pubkey>,
+    option: u8,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let (escrow, _) = find_escrow_address(program_id, mint, voter);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*voter, true),
+        AccountMeta::new(receipt, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(escrow, false),
+    ];
+    if let Some(voter_registry) = voter_registry {
+        let (membership, _) = find_member_address(program_id, voter_registry, voter);
+        accounts.push(AccountMeta::new_readonly(membership, false));
+    }
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::CastVote { option }, accounts)
+}
+
+pub fn close_poll(program_id: &Pubkey, poll: &Pubkey, authority: &Pubkey) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::ClosePoll,
+        vec![
+            AccountMeta::new(*poll, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+    )
+}
+
+/// `creator` receives the deposit of proposals that have one.
+pub fn finalize(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    electorate: Option<&Pubkey>,
+    creator: Option<&Pubkey>,
+    creator_escrow: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![AccountMeta::new(*poll, false)];
+    if let Some(electorate) = electorate {
+        accounts.push(AccountMeta::new_readonly(*electorate, false));
+    }
+    if let Some(creator) = creator {
+        accounts.push(AccountMeta::new(*creator, false));
+    }
+    if let Some(creator_escrow) = creator_escrow {
+        accounts.push(AccountMeta::new(*creator_escrow, false));
+    }
+
+    // Only read for ranked choice polls, after the accounts above
+    accounts.push(AccountMeta::new_readonly(
+        find_ranked_tally_address(program_id, poll).0,
+        false,
+    ));
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::Finalize, accounts)
+}
+
+pub fn deposit(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    voter: &Pubkey,
+    source: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (escrow, _) = find_escrow_address(program_id, mint, voter);
+    let (vault, _) = find_escrow_vault_address(program_id, mint, voter);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::Deposit { amount },
+        vec![
+            AccountMeta::new(escrow, false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(*source, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    )
+}
+
+pub fn withdraw(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    voter: &Pubkey,
+    destination: &Pubkey,
+    amount: u64,
+) -> Instruction {
+    let (escrow, _) = find_escrow_address(program_id, mint, voter);
+    let (vault, _) = find_escrow_vault_address(program_id, mint, voter);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::Withdraw { amount },
+        vec![
+            AccountMeta::new(escrow, false),
+            AccountMeta::new(vault, false),
+            AccountMeta::new_readonly(*voter, true),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ],
+    )
+}
+
+pub fn relinquish_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let (escrow, _) = find_escrow_address(program_id, mint, voter);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::RelinquishVote,
+        vec![
+            AccountMeta::new(*poll, false),
+            AccountMeta::new(*voter, true),
+            AccountMeta::new(receipt, false),
+            AccountMeta::new(escrow, false),
+        ],
+    )
+}
+
+pub fn change_vote(program_id: &Pubkey, poll: &Pubkey, voter: &Pubkey, option: u8) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::ChangeVote { option },
+        vec![
+            AccountMeta::new(*poll, false),
+            AccountMeta::new_readonly(*voter, true),
+            AccountMeta::new(receipt, false),
+        ],
+    )
+}
+
+pub fn retract_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    escrow_mint: Option<&Pubkey>,
+    delegators: &[Pubkey],
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*voter, true),
+        AccountMeta::new(receipt, false),
+    ];
+    if let Some(mint) = escrow_mint {
+        let (escrow, _) = find_escrow_address(program_id, mint, voter);
+        accounts.push(AccountMeta::new(escrow, false));
+    }
+    for delegator in delegators {
+        let (delegator_receipt, _) = find_receipt_address(program_id, poll, delegator);
+        accounts.push(AccountMeta::new(delegator_receipt, false));
+        if let Some(mint) = escrow_mint {
+            let (escrow, _) = find_escrow_address(program_id, mint, delegator);
+            accounts.push(AccountMeta::new(escrow, false));
+        }
+    }
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::RetractVote, accounts)
+}
+
+pub fn initialize_registry(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    registry_id: u64,
+) -> Instruction {
+    let (registry, _) = find_registry_address(program_id, authority, registry_id);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::InitializeRegistry { registry_id },
+        vec![
+            AccountMeta::new(registry, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn add_member(
+    program_id: &Pubkey,
+    registry: &Pubkey,
+    authority: &Pubkey,
+    member: &Pubkey,
+) -> Instruction {
+    let (membership, _) = find_member_address(program_id, registry, member);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::AddMember { member: *member },
+        vec![
+            AccountMeta::new(*registry, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(membership, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn remove_member(
+    program_id: &Pubkey,
+    registry: &Pubkey,
+    authority: &Pubkey,
+    member: &Pubkey,
+) -> Instruction {
+    let (membership, _) = find_member_address(program_id, registry, member);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::RemoveMember { member: *member },
+        vec![
+            AccountMeta::new(*registry, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(membership, false),
+        ],
+    )
+}
+
+pub fn cast_vote_with_proof(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    voter_registry: Option<&Pubkey>,
+    option: u8,
+    weight: u64,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*voter, true),
+        AccountMeta::new(receipt, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(voter_registry) = voter_registry {
+        let (membership, _) = find_member_address(program_id, voter_registry, voter);
+        accounts.push(AccountMeta::new_readonly(membership, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::CastVoteWithProof {
+            option,
+            weight,
+            proof,
+        },
+        accounts,
+    )
+}
+
+pub fn close_poll_account(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    authority: &Pubkey,
+    recipient: &Pubkey,
+) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::ClosePollAccount,
+        vec![
+            AccountMeta::new(*poll, false),
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*recipient, false),
+            AccountMeta::new_readonly(find_ranked_tally_address(program_id, poll).0, false),
+        ],
+    )
+}
+
+pub fn close_receipt(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    mint: Option<&Pubkey>,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(receipt, false),
+        AccountMeta::new(*voter, false),
+    ];
+    if let Some(mint) = mint {
+        let (escrow, _) = find_escrow_address(program_id, mint, voter);
+        accounts.push(AccountMeta::new(escrow, false));
+    }
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::CloseReceipt, accounts)
+}
+
+pub fn insert_transaction(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    authority: &Pubkey,
+    index: u16,
+    hold_up_time: u32,
+    instructions: Vec<InstructionData>,
+) -> Instruction {
+    let (proposal_transaction, _) = find_proposal_transaction_address(program_id, poll, index);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::InsertTransaction {
+            index,
+            hold_up_time,
+            instructions,
+        },
+        vec![
+            AccountMeta::new(*poll, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(proposal_transaction, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// `instructions` must be the instructions stored in the proposal transaction, their
+/// programs and accounts are passed along.
+/// `governance` is the poll authority or, for proposals, the realm.
+pub fn execute_proposal(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    governance: &Pubkey,
+    index: u16,
+    instructions: &[InstructionData],
+) -> Instruction {
+    let (proposal_transaction, _) = find_proposal_transaction_address(program_id, poll, index);
+    let (treasury, _) = find_treasury_address(program_id, governance);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(proposal_transaction, false),
+        AccountMeta::new(treasury, false),
+    ];
+    for instruction in instructions {
+        accounts.push(AccountMeta::new_readonly(instruction.program_id, false));
+        accounts.extend(
+            instruction
+                .accounts
+                .iter()
+                .filter(|meta| meta.pubkey != treasury)
+                .map(|meta| {
+                    if meta.is_writable {
+                        AccountMeta::new(meta.pubkey, false)
+                    } else {
+                        AccountMeta::new_readonly(meta.pubkey, false)
+                    }
+                }),
+        );
+    }
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::ExecuteProposal, accounts)
+}
+
+/// `ballot_count` is the poll's current ballot count, it selects the ballot chunk. The
+/// weight source is the voter's token account or escrow, depending on the poll.
+#[allow(clippy::too_many_arguments)]
+pub fn cast_ranked_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    ballot_count: u32,
+    weight_source: Option<&Pubkey>,
+    voter_registry: Option<&Pubkey>,
+    ranking: Vec<u8>,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let (ballot_chunk, _) =
+        find_ballot_chunk_address(program_id, poll, ballot_count / BALLOTS_PER_CHUNK);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*voter, true),
+        AccountMeta::new(receipt, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+        AccountMeta::new(ballot_chunk, false),
+    ];
+    if let Some(weight_source) = weight_source {
+        accounts.push(AccountMeta::new(*weight_source, false));
+    }
+    if let Some(voter_registry) = voter_registry {
+        let (membership, _) = find_member_address(program_id, voter_registry, voter);
+        accounts.push(AccountMeta::new_readonly(membership, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::CastRankedVote { ranking },
+        accounts,
+    )
+}
+
+/// `chunks` are the indexes of the ballot chunks to count, in order.
+pub fn tally(program_id: &Pubkey, poll: &Pubkey, payer: &Pubkey, chunks: &[u32]) -> Instruction {
+    let (ranked_tally, _) = find_ranked_tally_address(program_id, poll);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*poll, false),
+        AccountMeta::new(ranked_tally, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    accounts.extend(chunks.iter().map(|index| {
+        let (ballot_chunk, _) = find_ballot_chunk_address(program_id, poll, *index);
+        AccountMeta::new_readonly(ballot_chunk, false)
+    }));
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::Tally, accounts)
+}
+
+pub fn cast_approval_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    weight_source: Option<&Pubkey>,
+    voter_registry: Option<&Pubkey>,
+    options: Vec<u8>,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*voter, true),
+        AccountMeta::new(receipt, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(weight_source) = weight_source {
+        accounts.push(AccountMeta::new(*weight_source, false));
+    }
+    if let Some(voter_registry) = voter_registry {
+        let (membership, _) = find_member_address(program_id, voter_registry, voter);
+        accounts.push(AccountMeta::new_readonly(membership, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::CastApprovalVote { options },
+        accounts,
+    )
+}
+
+pub fn cast_quadratic_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    weight_source: Option<&Pubkey>,
+    voter_registry: Option<&Pubkey>,
+    allocations: Vec<VoiceCreditAllocation>,
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*voter, true),
+        AccountMeta::new(receipt, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(weight_source) = weight_source {
+        accounts.push(AccountMeta::new(*weight_source, false));
+    }
+    if let Some(voter_registry) = voter_registry {
+        let (membership, _) = find_member_address(program_id, voter_registry, voter);
+        accounts.push(AccountMeta::new_readonly(membership, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::CastQuadraticVote { allocations },
+        accounts,
+    )
+}
+
+pub fn commit_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    weight_source: Option<&Pubkey>,
+    voter_registry: Option<&Pubkey>,
+    commitment: [u8; 32],
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*voter, true),
+        AccountMeta::new(receipt, false),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(weight_source) = weight_source {
+        accounts.push(AccountMeta::new(*weight_source, false));
+    }
+    if let Some(voter_registry) = voter_registry {
+        let (membership, _) = find_member_address(program_id, voter_registry, voter);
+        accounts.push(AccountMeta::new_readonly(membership, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::CommitVote { commitment },
+        accounts,
+    )
+}
+
+pub fn reveal_vote(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    voter: &Pubkey,
+    option: u8,
+    salt: [u8; 32],
+) -> Instruction {
+    let (receipt, _) = find_receipt_address(program_id, poll, voter);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::RevealVote { option, salt },
+        vec![
+            AccountMeta::new(*poll, false),
+            AccountMeta::new_readonly(*voter, true),
+            AccountMeta::new(receipt, false),
+        ],
+    )
+}
+
+/// Accounts to append to a vote instruction of the delegate for each delegator it votes
+/// for, `weight_source` being the delegator's token account or voter escrow.
+pub fn delegator_accounts(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    delegator: &Pubkey,
+    scope: &DelegationScope,
+    weight_source: Option<&Pubkey>,
+    voter_registry: Option<&Pubkey>,
+) -> Vec<AccountMeta> {
+    let (delegation, _) = find_delegation_address(program_id, delegator, scope);
+    let (receipt, _) = find_receipt_address(program_id, poll, delegator);
+    let mut accounts = vec![
+        AccountMeta::new_readonly(delegation, false),
+        AccountMeta::new(receipt, false),
+    ];
+    if let Some(weight_source) = weight_source {
+        accounts.push(AccountMeta::new(*weight_source, false));
+    }
+    if let Some(voter_registry) = voter_registry {
+        let (membership, _) = find_member_address(program_id, voter_registry, delegator);
+        accounts.push(AccountMeta::new_readonly(membership, false));
+    }
+    accounts
+}
+
+pub fn delegate(
+    program_id: &Pubkey,
+    delegator: &Pubkey,
+    delegate: &Pubkey,
+    scope: DelegationScope,
+) -> Instruction {
+    let (delegation, _) = find_delegation_address(program_id, delegator, &scope);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::Delegate {
+            delegate: *delegate,
+            scope,
+        },
+        vec![
+            AccountMeta::new(*delegator, true),
+            AccountMeta::new(delegation, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+pub fn revoke_delegation(
+    program_id: &Pubkey,
+    delegator: &Pubkey,
+    scope: &DelegationScope,
+) -> Instruction {
+    let (delegation, _) = find_delegation_address(program_id, delegator, scope);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::RevokeDelegation,
+        vec![
+            AccountMeta::new(*delegator, true),
+            AccountMeta::new(delegation, false),
+        ],
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_realm(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    name: String,
+    governance_mint: &Pubkey,
+    voting_duration: u32,
+    quorum: Quorum,
+    threshold: Threshold,
+    min_proposal_weight: u64,
+    proposal_deposit: u64,
+) -> Instruction {
+    let (realm, _) = find_realm_address(program_id, &name);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::CreateRealm {
+            name,
+            governance_mint: *governance_mint,
+            voting_duration,
+            quorum,
+            threshold,
+            min_proposal_weight,
+            proposal_deposit,
+        },
+        vec![
+            AccountMeta::new(realm, false),
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// `proposal_index` is the realm's current proposal count.
+#[allow(clippy::too_many_arguments)]
+pub fn create_proposal(
+    program_id: &Pubkey,
+    realm: &Pubkey,
+    creator: &Pubkey,
+    proposal_index: u64,
+    title: String,
+    description_uri: String,
+    content_hash: [u8; 32],
+    options: Vec<String>,
+    start_ts: i64,
+    ballot_type: BallotType,
+    creator_escrow: Option<&Pubkey>,
+) -> Instruction {
+    let (poll, _) = find_poll_address(program_id, realm, proposal_index);
+    let mut accounts = vec![
+        AccountMeta::new(*realm, false),
+        AccountMeta::new(poll, false),
+        AccountMeta::new(*creator, true),
+        AccountMeta::new_readonly(system_program::id(), false),
+    ];
+    if let Some(creator_escrow) = creator_escrow {
+        accounts.push(AccountMeta::new(*creator_escrow, false));
+    }
+
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::CreateProposal {
+            title,
+            description_uri,
+            content_hash,
+            options,
+            start_ts,
+            ballot_type,
+        },
+        accounts,
+    )
+}
+
+/// `realm` is only passed when the realm authority cancels someone else's proposal.
+pub fn cancel_proposal(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    signer: &Pubkey,
+    realm: Option<&Pubkey>,
+    creator_escrow: Option<&Pubkey>,
+) -> Instruction {
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new(*signer, true),
+    ];
+    if let Some(realm) = realm {
+        let (treasury, _) = find_treasury_address(program_id, realm);
+        accounts.push(AccountMeta::new_readonly(*realm, false));
+        accounts.push(AccountMeta::new(treasury, false));
+    }
+    if let Some(creator_escrow) = creator_escrow {
+        accounts.push(AccountMeta::new(*creator_escrow, false));
+    }
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::CancelProposal, accounts)
+}
+
+pub fn sign_off(program_id: &Pubkey, poll: &Pubkey, authority: &Pubkey) -> Instruction {
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::SignOff,
+        vec![
+            AccountMeta::new(*poll, false),
+            AccountMeta::new_readonly(*authority, true),
+        ],
+    )
+}
+
+pub fn close_proposal_transaction(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    authority: &Pubkey,
+    index: u16,
+) -> Instruction {
+    let (proposal_transaction, _) = find_proposal_transaction_address(program_id, poll, index);
+    Instruction::new_with_borsh(
+        *program_id,
+        &VoteInstruction::CloseProposalTransaction,
+        vec![
+            AccountMeta::new(*poll, false),
+            AccountMeta::new(proposal_transaction, false),
+            AccountMeta::new(*authority, false),
+        ],
+    )
+}
+
+pub fn close_ballots(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    authority: &Pubkey,
+    recipient: &Pubkey,
+    chunks: &[u32],
+) -> Instruction {
+    let (ranked_tally, _) = find_ranked_tally_address(program_id, poll);
+    let mut accounts = vec![
+        AccountMeta::new(*poll, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*recipient, false),
+        AccountMeta::new(ranked_tally, false),
+    ];
+    accounts.extend(chunks.iter().map(|index| {
+        let (ballot_chunk, _) = find_ballot_chunk_address(program_id, poll, *index);
+        AccountMeta::new(ballot_chunk, false)
+    }));
+
+    Instruction::new_with_borsh(*program_id, &VoteInstruction::CloseBallots, accounts)
+}
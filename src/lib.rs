@@ -1,20 +1,22 @@
-use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
-    account_info::{next_account_info, AccountInfo},
-    entrypoint,
-    entrypoint::ProgramResult,
-    msg,
-    program_error::ProgramError,
-    pubkey::Pubkey,
+    account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, msg,
+    program_error::PrintProgramError, pubkey::Pubkey,
 };
 
-#[derive(BorshSerialize, BorshDeserialize, Debug)]
-pub struct VoteAccount {
-    pub yes: u32,
-    pub abstained: u32,
-    pub no: u32,
-}
+pub mod accounts;
+pub mod error;
+pub mod instruction;
+pub mod merkle;
+pub mod processor;
+pub mod state;
+mod utils;
+
+#[cfg(test)]
+mod test_utils;
+
+use crate::{error::VoteError, processor::Processor};
 
+#[cfg(not(feature = "no-entrypoint"))]
 entrypoint!(process_instruction);
 
 pub fn process_instruction(
@@ -24,27 +26,12 @@ pub fn process_instruction(
 ) -> ProgramResult {
     msg!("Rust vote program entrypoint");
 
-    let accounts_iter = &mut accounts.iter();
-    let account = next_account_info(accounts_iter)?;
-
-    // The account must be owned by the program in order to modify its data
-    if account.owner != program_id {
-        msg!("Vote account does not have the correct program id");
-        return Err(ProgramError::IncorrectProgramId);
-    }
-
-    let mut vote_account = VoteAccount::try_from_slice(&account.data.borrow())?;
-    match instruction_data[0] {
-        0 => vote_account.yes += 1,
-        1 => vote_account.abstained += 1,
-        2 => vote_account.no += 1,
-        _ => msg!("Unknown vote type")
+    if let Err(error) = Processor::process(program_id, accounts, instruction_data) {
+        // Log the human readable description of custom program errors
+        error.print::<VoteError>();
+        return Err(error);
     }
 
-    vote_account.serialize(&mut &mut account.data.borrow_mut()[..])?;
-
-    msg!("Votes: yes:{}, abstained: {}, no:{}", vote_account.yes, vote_account.abstained, vote_account.no);
-
     Ok(())
 }
 
@@ -52,44 +39,63 @@ pub fn process_instruction(
 #[cfg(test)]
 mod test {
     use super::*;
-    use solana_program::clock::Epoch;
+    use crate::{
+        instruction::{cast_vote, initialize_poll},
+        state::{
+            find_poll_address, find_receipt_address, BallotType, Poll, Quorum, Threshold,
+            VoteWeight,
+        },
+        test_utils::{setup, system_program_account, uncreated, wallet},
+    };
+    use borsh::BorshDeserialize;
 
     #[test]
     fn test_sanity() {
-        let program_id = Pubkey::default();
-        let key = Pubkey::default();
-        let mut lamports = 0;
-        let mut data = VoteAccount {
-            yes: 0,
-            abstained: 0,
-            no: 0,
-        }.try_to_vec().unwrap();
-        let owner = Pubkey::default();
-        let account = AccountInfo::new(
-            &key,
-            false,
-            true,
-            &mut lamports,
-            &mut data,
-            &owner,
-            false,
-            Epoch::default(),
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let (poll_key, _) = find_poll_address(&program_id, creator.key, 0);
+        let poll = uncreated(poll_key);
+        let system = system_program_account();
+
+        let init = initialize_poll(
+            &program_id,
+            creator.key,
+            0,
+            "Sanity".into(),
+            "https://vote.hanmaster.ru/sanity".into(),
+            [0; 32],
+            vec!["yes".into(), "abstained".into(), "no".into()],
+            0,
+            100,
+            VoteWeight::OnePerWallet,
+            None,
+            Quorum::None,
+            Threshold::SimpleMajority,
+            BallotType::SingleChoice,
+            None,
         );
-        let instruction_data_0: Vec<u8> = vec![0];
-        let instruction_data_1: Vec<u8> = vec![1];
-        let instruction_data_2: Vec<u8> = vec![2];
-
-        let accounts = vec![account];
-
-        assert_eq!(VoteAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().yes, 0);
-
-        process_instruction(&program_id, &accounts, &instruction_data_0).unwrap();
-        assert_eq!(VoteAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().yes, 1);
-
-        process_instruction(&program_id, &accounts, &instruction_data_1).unwrap();
-        assert_eq!(VoteAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().abstained, 1);
-
-        process_instruction(&program_id, &accounts, &instruction_data_2).unwrap();
-        assert_eq!(VoteAccount::try_from_slice(&accounts[0].data.borrow()).unwrap().no, 1);
+        process_instruction(
+            &program_id,
+            &[poll.clone(), creator, system.clone()],
+            &init.data,
+        )
+        .unwrap();
+
+        for option in 0..3 {
+            let voter = wallet();
+            let (receipt_key, _) = find_receipt_address(&program_id, &poll_key, voter.key);
+            let vote = cast_vote(&program_id, &poll_key, voter.key, None, None, option);
+            process_instruction(
+                &program_id,
+                &[poll.clone(), voter, uncreated(receipt_key), system.clone()],
+                &vote.data,
+            )
+            .unwrap();
+        }
+
+        let poll = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        let votes: Vec<u64> = poll.options.iter().map(|option| option.votes).collect();
+        assert_eq!(votes, vec![1, 1, 1]);
     }
 }
@@ -0,0 +1,36 @@
+//! Merkle allowlists of (voter, weight) leaves.
+//!
+//! Leaves and inner nodes are keccak-256 hashes with distinct prefixes, so a node can
+//! never be passed off as a leaf. Inner nodes hash their children in sorted order, which
+//! lets proofs be plain lists of sibling hashes without left/right flags.
+
+use solana_program::{keccak, pubkey::Pubkey};
+
+/// Longest proof accepted, enough for 2^32 leaves
+pub const MAX_PROOF_LEN: usize = 32;
+
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+pub fn leaf_hash(voter: &Pubkey, weight: u64) -> [u8; 32] {
+    keccak::hashv(&[LEAF_PREFIX, voter.as_ref(), &weight.to_le_bytes()]).to_bytes()
+}
+
+pub fn node_hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    keccak::hashv(&[NODE_PREFIX, left, right]).to_bytes()
+}
+
+/// Whether `proof` leads from the leaf of (voter, weight) to `root`.
+pub fn verify_proof(root: &[u8; 32], voter: &Pubkey, weight: u64, proof: &[[u8; 32]]) -> bool {
+    if proof.len() > MAX_PROOF_LEN {
+        return false;
+    }
+
+    let computed = proof
+        .iter()
+        .fold(leaf_hash(voter, weight), |node, sibling| {
+            node_hash(&node, sibling)
+        });
+    computed == *root
+}
@@ -0,0 +1,5030 @@
+use borsh::BorshSerialize;
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    borsh::{get_instance_packed_len, try_from_slice_unchecked},
+    clock::Clock,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    system_instruction,
+    sysvar::Sysvar,
+};
+
+use crate::{
+    accounts::{
+        check_address, check_owner, check_rent_exempt, check_writable, AddMemberAccounts,
+        CancelProposalAccounts, CastVoteAccounts, ChangeVoteAccounts, CloseBallotsAccounts,
+        ClosePollAccountAccounts, ClosePollAccounts, CloseProposalTransactionAccounts,
+        CloseReceiptAccounts, CreateProposalAccounts, CreateRealmAccounts, DelegateAccounts,
+        DepositAccounts, ExecuteProposalAccounts, FinalizeAccounts, InitializePollAccounts,
+        InitializeRegistryAccounts, InsertTransactionAccounts, RelinquishVoteAccounts,
+        RemoveMemberAccounts, RetractVoteAccounts, RevealVoteAccounts, RevokeDelegationAccounts,
+        SignOffAccounts, TallyAccounts, WithdrawAccounts,
+    },
+    error::VoteError,
+    instruction::VoteInstruction,
+    merkle,
+    state::{
+        find_ballot_chunk_address, find_delegation_address, find_escrow_address,
+        find_escrow_vault_address, find_member_address, find_poll_address,
+        find_proposal_transaction_address, find_ranked_tally_address, find_realm_address,
+        find_receipt_address, find_registry_address, find_treasury_address, selected_options,
+        vote_commitment, AccountType, BallotChunk, BallotType, Delegation, DelegationScope,
+        InstructionData, Outcome, Poll, ProposalState, ProposalTransaction, Quorum, RankedBallot,
+        RankedTally, Realm, RegistryMember, Threshold, VoiceCreditAllocation, VoteOption,
+        VoteReceipt, VoteWeight, VoterEscrow, VoterRegistry, BALLOTS_PER_CHUNK, BALLOT_CHUNK_SEED,
+        DELEGATION_SEED, ESCROW_SEED, ESCROW_VAULT_SEED, MAX_DESCRIPTION_URI_LEN, MAX_OPTIONS,
+        MAX_OPTION_LABEL_LEN, MAX_REALM_NAME_LEN, MAX_TITLE_LEN, MEMBER_SEED, MIN_OPTIONS,
+        POLL_SEED, PROPOSAL_TRANSACTION_SEED, RANKED_TALLY_SEED, REALM_SEED, RECEIPT_SEED,
+        REGISTRY_SEED, TREASURY_SEED,
+    },
+    utils::{close_account, create_pda_account, transfer_lamports},
+};
+
+pub struct Processor;
+
+impl Processor {
+    pub fn process(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        instruction_data: &[u8],
+    ) -> ProgramResult {
+        let instruction = VoteInstruction::unpack(instruction_data)?;
+
+        match instruction {
+            VoteInstruction::InitializePoll {
+                poll_id,
+                title,
+                description_uri,
+                content_hash,
+                options,
+                start_ts,
+                end_ts,
+                vote_weight,
+                voter_registry,
+                quorum,
+                threshold,
+                ballot_type,
+                reveal_end_ts,
+            } => {
+                msg!("Instruction: InitializePoll");
+                Self::process_initialize_poll(
+                    program_id,
+                    accounts,
+                    poll_id,
+                    title,
+                    description_uri,
+                    content_hash,
+                    options,
+                    start_ts,
+                    end_ts,
+                    vote_weight,
+                    voter_registry,
+                    quorum,
+                    threshold,
+                    ballot_type,
+                    reveal_end_ts,
+                )
+            }
+            VoteInstruction::CastVote { option } => {
+                msg!("Instruction: CastVote");
+                Self::process_cast_vote(program_id, accounts, Ballot::Single(option), None)
+            }
+            VoteInstruction::ClosePoll => {
+                msg!("Instruction: ClosePoll");
+                Self::process_close_poll(program_id, accounts)
+            }
+            VoteInstruction::Finalize => {
+                msg!("Instruction: Finalize");
+                Self::process_finalize(program_id, accounts)
+            }
+            VoteInstruction::DeprecatedMigratePoll => {
+                msg!("Instruction: DeprecatedMigratePoll");
+                Err(ProgramError::InvalidInstructionData)
+            }
+            VoteInstruction::Deposit { amount } => {
+                msg!("Instruction: Deposit");
+                Self::process_deposit(program_id, accounts, amount)
+            }
+            VoteInstruction::Withdraw { amount } => {
+                msg!("Instruction: Withdraw");
+                Self::process_withdraw(program_id, accounts, amount)
+            }
+            VoteInstruction::RelinquishVote => {
+                msg!("Instruction: RelinquishVote");
+                Self::process_relinquish_vote(program_id, accounts)
+            }
+            VoteInstruction::ChangeVote { option } => {
+                msg!("Instruction: ChangeVote");
+                Self::process_change_vote(program_id, accounts, option)
+            }
+            VoteInstruction::RetractVote => {
+                msg!("Instruction: RetractVote");
+                Self::process_retract_vote(program_id, accounts)
+            }
+            VoteInstruction::InitializeRegistry { registry_id } => {
+                msg!("Instruction: InitializeRegistry");
+                Self::process_initialize_registry(program_id, accounts, registry_id)
+            }
+            VoteInstruction::AddMember { member } => {
+                msg!("Instruction: AddMember");
+                Self::process_add_member(program_id, accounts, &member)
+            }
+            VoteInstruction::RemoveMember { member } => {
+                msg!("Instruction: RemoveMember");
+                Self::process_remove_member(program_id, accounts, &member)
+            }
+            VoteInstruction::ClosePollAccount => {
+                msg!("Instruction: ClosePollAccount");
+                Self::process_close_poll_account(program_id, accounts)
+            }
+            VoteInstruction::CloseReceipt => {
+                msg!("Instruction: CloseReceipt");
+                Self::process_close_receipt(program_id, accounts)
+            }
+            VoteInstruction::CastVoteWithProof {
+                option,
+                weight,
+                proof,
+            } => {
+                msg!("Instruction: CastVoteWithProof");
+                Self::process_cast_vote(
+                    program_id,
+                    accounts,
+                    Ballot::Single(option),
+                    Some((weight, &proof)),
+                )
+            }
+            VoteInstruction::InsertTransaction {
+                index,
+                hold_up_time,
+                instructions,
+            } => {
+                msg!("Instruction: InsertTransaction");
+                Self::process_insert_transaction(
+                    program_id,
+                    accounts,
+                    index,
+                    hold_up_time,
+                    instructions,
+                )
+            }
+            VoteInstruction::ExecuteProposal => {
+                msg!("Instruction: ExecuteProposal");
+                Self::process_execute_proposal(program_id, accounts)
+            }
+            VoteInstruction::CastRankedVote { ranking } => {
+                msg!("Instruction: CastRankedVote");
+                Self::process_cast_vote(program_id, accounts, Ballot::Ranked(&ranking), None)
+            }
+            VoteInstruction::Tally => {
+                msg!("Instruction: Tally");
+                Self::process_tally(program_id, accounts)
+            }
+            VoteInstruction::CastApprovalVote { options } => {
+                msg!("Instruction: CastApprovalVote");
+                Self::process_cast_vote(program_id, accounts, Ballot::Approval(&options), None)
+            }
+            VoteInstruction::CastQuadraticVote { allocations } => {
+                msg!("Instruction: CastQuadraticVote");
+                Self::process_cast_vote(program_id, accounts, Ballot::Quadratic(&allocations), None)
+            }
+            VoteInstruction::CommitVote { commitment } => {
+                msg!("Instruction: CommitVote");
+                Self::process_cast_vote(program_id, accounts, Ballot::Commitment(commitment), None)
+            }
+            VoteInstruction::RevealVote { option, salt } => {
+                msg!("Instruction: RevealVote");
+                Self::process_reveal_vote(program_id, accounts, option, &salt)
+            }
+            VoteInstruction::Delegate { delegate, scope } => {
+                msg!("Instruction: Delegate");
+                Self::process_delegate(program_id, accounts, &delegate, scope)
+            }
+            VoteInstruction::RevokeDelegation => {
+                msg!("Instruction: RevokeDelegation");
+                Self::process_revoke_delegation(program_id, accounts)
+            }
+            VoteInstruction::CreateRealm {
+                name,
+                governance_mint,
+                voting_duration,
+                quorum,
+                threshold,
+                min_proposal_weight,
+                proposal_deposit,
+            } => {
+                msg!("Instruction: CreateRealm");
+                Self::process_create_realm(
+                    program_id,
+                    accounts,
+                    name,
+                    governance_mint,
+                    voting_duration,
+                    quorum,
+                    threshold,
+                    min_proposal_weight,
+                    proposal_deposit,
+                )
+            }
+            VoteInstruction::CreateProposal {
+                title,
+                description_uri,
+                content_hash,
+                options,
+                start_ts,
+                ballot_type,
+            } => {
+                msg!("Instruction: CreateProposal");
+                Self::process_create_proposal(
+                    program_id,
+                    accounts,
+                    title,
+                    description_uri,
+                    content_hash,
+                    options,
+                    start_ts,
+                    ballot_type,
+                )
+            }
+            VoteInstruction::CancelProposal => {
+                msg!("Instruction: CancelProposal");
+                Self::process_cancel_proposal(program_id, accounts)
+            }
+            VoteInstruction::SignOff => {
+                msg!("Instruction: SignOff");
+                Self::process_sign_off(program_id, accounts)
+            }
+            VoteInstruction::CloseProposalTransaction => {
+                msg!("Instruction: CloseProposalTransaction");
+                Self::process_close_proposal_transaction(program_id, accounts)
+            }
+            VoteInstruction::CloseBallots => {
+                msg!("Instruction: CloseBallots");
+                Self::process_close_ballots(program_id, accounts)
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn process_initialize_poll(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        poll_id: u64,
+        title: String,
+        description_uri: String,
+        content_hash: [u8; 32],
+        options: Vec<String>,
+        start_ts: i64,
+        end_ts: i64,
+        vote_weight: VoteWeight,
+        voter_registry: Option<Pubkey>,
+        quorum: Quorum,
+        threshold: Threshold,
+        ballot_type: BallotType,
+        reveal_end_ts: Option<i64>,
+    ) -> ProgramResult {
+        let InitializePollAccounts {
+            poll: poll_info,
+            creator: creator_info,
+            system_program: system_program_info,
+            voter_registry: voter_registry_info,
+        } = InitializePollAccounts::try_from((program_id, accounts))?;
+
+        let poll = Poll {
+            account_type: AccountType::Poll,
+            authority: *creator_info.key,
+            poll_id,
+            title,
+            description_uri,
+            content_hash,
+            options: options
+                .into_iter()
+                .map(|label| VoteOption { label, votes: 0 })
+                .collect(),
+            start_ts,
+            end_ts,
+            vote_weight,
+            voter_registry,
+            open_receipts: 0,
+            participating_weight: 0,
+            quorum,
+            threshold,
+            outcome: Outcome::Pending,
+            state: ProposalState::Voting,
+            ballot_type,
+            ballot_count: 0,
+            reveal_end_ts,
+            unrevealed_commits: 0,
+            realm: None,
+            deposit: 0,
+            creator_escrow: None,
+            transaction_count: 0,
+            executed_transaction_count: 0,
+            is_closed: false,
+            is_finalized: false,
+        };
+        check_poll_config(&poll)?;
+
+        let (poll_address, bump) = find_poll_address(program_id, creator_info.key, poll_id);
+        check_address(poll_info, &poll_address)?;
+
+        // An existing poll must never be re-created, otherwise its tally would be reset
+        if poll_info.owner == program_id {
+            msg!("Poll account is already in use");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        if let Some(voter_registry) = &voter_registry {
+            let voter_registry_info =
+                voter_registry_info.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            check_address(voter_registry_info, voter_registry)?;
+            let registry = load_registry(program_id, voter_registry_info)?;
+            if registry.authority != *creator_info.key {
+                msg!("Voter registry is managed by another authority");
+                return Err(VoteError::InvalidAuthority.into());
+            }
+        }
+
+        create_pda_account(
+            creator_info,
+            poll_info,
+            system_program_info,
+            program_id,
+            get_instance_packed_len(&poll)?,
+            &[
+                POLL_SEED,
+                creator_info.key.as_ref(),
+                &poll_id.to_le_bytes(),
+                &[bump],
+            ],
+        )?;
+
+        check_rent_exempt(poll_info)?;
+
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    /// Casts a vote, `proven_weight` is the claimed weight and its Merkle proof for
+    /// allowlist polls.
+    fn process_cast_vote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        ballot: Ballot,
+        proven_weight: Option<(u64, &[[u8; 32]])>,
+    ) -> ProgramResult {
+        let CastVoteAccounts {
+            poll: poll_info,
+            voter: voter_info,
+            receipt: receipt_info,
+            receipt_bump: bump,
+            system_program: system_program_info,
+            remaining,
+        } = CastVoteAccounts::try_from((program_id, accounts))?;
+        let remaining = &mut remaining.iter();
+
+        let mut poll = load_poll(poll_info)?;
+        check_voting_open(&poll)?;
+
+        if receipt_info.owner == program_id {
+            return Err(VoteError::AlreadyVoted.into());
+        }
+
+        // Commit-reveal polls only accept commitments
+        if poll.reveal_end_ts.is_some() != matches!(ballot, Ballot::Commitment(_)) {
+            return Err(VoteError::UnsupportedBallotType.into());
+        }
+        let ballot_chunk_info = match (&ballot, poll.ballot_type) {
+            (Ballot::Single(_) | Ballot::Commitment(_), BallotType::SingleChoice) => None,
+            (Ballot::Ranked(_), BallotType::RankedChoice) => Some(next_account_info(remaining)?),
+            (Ballot::Approval(_), BallotType::Approval { .. }) => None,
+            (Ballot::Quadratic(_), BallotType::Quadratic { .. }) => None,
+            _ => return Err(VoteError::UnsupportedBallotType.into()),
+        };
+
+        let mut weight = match &poll.vote_weight {
+            VoteWeight::MerkleAllowlist { root } => {
+                let (weight, proof) = proven_weight.ok_or(VoteError::MerkleProofRequired)?;
+                if !merkle::verify_proof(root, voter_info.key, weight, proof) {
+                    return Err(VoteError::InvalidMerkleProof.into());
+                }
+                weight
+            }
+            _ => voter_weight(program_id, &poll, voter_info.key, remaining)?,
+        };
+        if proven_weight.is_some()
+            && !matches!(poll.vote_weight, VoteWeight::MerkleAllowlist { .. })
+        {
+            msg!("Poll does not use a Merkle allowlist");
+            return Err(ProgramError::InvalidInstructionData);
+        }
+        check_membership(program_id, &poll, voter_info.key, remaining)?;
+
+        // Delegators vote through the voter, their receipts keep them from voting themselves
+        let mut delegator_count = 0;
+        while let Some(delegation_info) = remaining.next() {
+            if matches!(poll.vote_weight, VoteWeight::MerkleAllowlist { .. }) {
+                // Delegated weights would need proofs of their own
+                return Err(VoteError::MerkleProofRequired.into());
+            }
+            let delegation = load_delegation(program_id, delegation_info)?;
+            let in_scope = match delegation.scope {
+                DelegationScope::All => true,
+                DelegationScope::Poll { poll } => poll == *poll_info.key,
+                DelegationScope::Realm { realm } => poll.realm == Some(realm),
+            };
+            if delegation.delegate != *voter_info.key || !in_scope {
+                return Err(VoteError::InvalidDelegation.into());
+            }
+
+            let delegator_receipt_info = next_account_info(remaining)?;
+            check_writable(delegator_receipt_info)?;
+            let (delegator_receipt_address, delegator_bump) =
+                find_receipt_address(program_id, poll_info.key, &delegation.delegator);
+            check_address(delegator_receipt_info, &delegator_receipt_address)?;
+            if delegator_receipt_info.owner == program_id {
+                return Err(VoteError::AlreadyVoted.into());
+            }
+
+            let delegated_weight =
+                voter_weight(program_id, &poll, &delegation.delegator, remaining)?;
+            check_membership(program_id, &poll, &delegation.delegator, remaining)?;
+            weight = weight
+                .checked_add(delegated_weight)
+                .ok_or(VoteError::CounterOverflow)?;
+
+            let delegator_receipt = VoteReceipt {
+                account_type: AccountType::VoteReceipt,
+                poll: *poll_info.key,
+                voter: delegation.delegator,
+                weight: delegated_weight,
+                delegate: Some(*voter_info.key),
+                ..VoteReceipt::default()
+            };
+            create_pda_account(
+                voter_info,
+                delegator_receipt_info,
+                system_program_info,
+                program_id,
+                get_instance_packed_len(&delegator_receipt)?,
+                &[
+                    RECEIPT_SEED,
+                    poll_info.key.as_ref(),
+                    delegation.delegator.as_ref(),
+                    &[delegator_bump],
+                ],
+            )?;
+            delegator_receipt.serialize(&mut &mut delegator_receipt_info.data.borrow_mut()[..])?;
+            poll.open_receipts = poll
+                .open_receipts
+                .checked_add(1)
+                .ok_or(VoteError::CounterOverflow)?;
+            delegator_count += 1;
+        }
+        if weight == 0 {
+            return Err(VoteError::NoVoteWeight.into());
+        }
+
+        let selections = match ballot {
+            Ballot::Single(option) => select(&poll, 0, option)?,
+            Ballot::Ranked(ranking) => {
+                let ballot_chunk_info =
+                    ballot_chunk_info.ok_or(ProgramError::NotEnoughAccountKeys)?;
+                store_ranked_ballot(
+                    program_id,
+                    &mut poll,
+                    poll_info,
+                    ballot_chunk_info,
+                    voter_info,
+                    system_program_info,
+                    RankedBallot {
+                        weight,
+                        ranking: ranking.to_vec(),
+                    },
+                )?;
+                // Until the poll is tallied the ballot counts for its first preference
+                select(&poll, 0, ranking[0])?
+            }
+            Ballot::Approval(options) => approval_selections(&poll, options)?,
+            Ballot::Quadratic(allocations) => quadratic_selections(&poll, allocations, weight)?,
+            // Counted once revealed
+            Ballot::Commitment(_) => {
+                poll.unrevealed_commits = poll
+                    .unrevealed_commits
+                    .checked_add(1)
+                    .ok_or(VoteError::CounterOverflow)?;
+                0
+            }
+        };
+        let receipt = VoteReceipt {
+            account_type: AccountType::VoteReceipt,
+            poll: *poll_info.key,
+            voter: *voter_info.key,
+            // Commitments select nothing until they are revealed
+            option: selected_options(selections).next().unwrap_or_default() as u8,
+            weight,
+            selections,
+            allocations: match ballot {
+                Ballot::Quadratic(allocations) => allocations.to_vec(),
+                _ => Vec::new(),
+            },
+            commitment: match ballot {
+                Ballot::Commitment(commitment) => Some(commitment),
+                _ => None,
+            },
+            delegate: None,
+            delegator_count,
+        };
+
+        for (option, votes) in receipt.tallied_votes() {
+            poll.options[option].add_votes(votes)?;
+        }
+        if receipt.commitment.is_none() {
+            poll.participating_weight = poll
+                .participating_weight
+                .checked_add(weight)
+                .ok_or(VoteError::CounterOverflow)?;
+        }
+        poll.open_receipts = poll
+            .open_receipts
+            .checked_add(1)
+            .ok_or(VoteError::CounterOverflow)?;
+
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        create_pda_account(
+            voter_info,
+            receipt_info,
+            system_program_info,
+            program_id,
+            get_instance_packed_len(&receipt)?,
+            &[
+                RECEIPT_SEED,
+                poll_info.key.as_ref(),
+                voter_info.key.as_ref(),
+                &[bump],
+            ],
+        )?;
+        receipt.serialize(&mut &mut receipt_info.data.borrow_mut()[..])?;
+
+        for vote_option in &poll.options {
+            msg!("Votes: {}: {}", vote_option.label, vote_option.votes);
+        }
+
+        Ok(())
+    }
+
+    fn process_close_poll(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let ClosePollAccounts {
+            poll: poll_info,
+            authority: authority_info,
+        } = ClosePollAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        check_authority(&poll, authority_info)?;
+        // The creator would otherwise freeze a favorable tally and pass the proposal
+        if poll.realm.is_some() {
+            msg!("Realm proposals can only be cancelled");
+            return Err(VoteError::InvalidProposalState.into());
+        }
+        check_state(&poll, ProposalState::Voting)?;
+        // Once the tally is final the poll is decided by finalizing it
+        if Clock::get()?.unix_timestamp >= poll.tally_end_ts() {
+            return Err(VoteError::Ended.into());
+        }
+
+        poll.cancel();
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        msg!("Outcome: {:?}", poll.outcome);
+
+        Ok(())
+    }
+
+    fn process_finalize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let FinalizeAccounts {
+            poll: poll_info,
+            remaining,
+        } = FinalizeAccounts::try_from((program_id, accounts))?;
+        let remaining = &mut remaining.iter();
+
+        let mut poll = load_poll(poll_info)?;
+        if poll.is_finalized {
+            return Err(VoteError::AlreadyFinalized.into());
+        }
+        check_state(&poll, ProposalState::Voting)?;
+        if Clock::get()?.unix_timestamp < poll.tally_end_ts() {
+            return Err(VoteError::VotingNotEnded.into());
+        }
+
+        let total_weight = match poll.quorum {
+            Quorum::Percent { .. } => {
+                total_weight(program_id, &poll, next_account_info(remaining)?)?
+            }
+            _ => 0,
+        };
+
+        // The deposit is only held until the proposal is resolved
+        if poll.deposit > 0 {
+            let creator_info = next_account_info(remaining)?;
+            if *creator_info.key != poll.authority {
+                return Err(VoteError::InvalidAuthority.into());
+            }
+            check_writable(creator_info)?;
+            transfer_lamports(poll_info, creator_info, poll.deposit)?;
+            poll.deposit = 0;
+        }
+        unlock_creator_escrow(program_id, &poll, remaining)?;
+
+        poll.outcome = poll.tally_outcome(total_weight);
+        if poll.ballot_type == BallotType::RankedChoice {
+            let ranked_tally_info = next_account_info(remaining)?;
+            let (ranked_tally_address, _) = find_ranked_tally_address(program_id, poll_info.key);
+            check_address(ranked_tally_info, &ranked_tally_address)?;
+            check_owner(ranked_tally_info, program_id)?;
+            let ranked_tally = load_ranked_tally(ranked_tally_info)?;
+            if !ranked_tally.is_complete {
+                return Err(VoteError::TallyNotComplete.into());
+            }
+            // The first preferences only open the runoff, its winner decides
+            if poll.outcome != Outcome::QuorumNotMet {
+                poll.outcome = if ranked_tally.winner == Some(0) {
+                    Outcome::Passed
+                } else {
+                    Outcome::Rejected
+                };
+            }
+        }
+        poll.state = if poll.outcome == Outcome::Passed {
+            // Nothing left to execute without proposal transactions
+            if poll.transaction_count == 0 {
+                ProposalState::Executed
+            } else {
+                ProposalState::Succeeded
+            }
+        } else {
+            ProposalState::Defeated
+        };
+        poll.is_finalized = true;
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        for vote_option in &poll.options {
+            msg!("Result: {}: {}", vote_option.label, vote_option.votes);
+        }
+        msg!("Outcome: {:?}", poll.outcome);
+
+        Ok(())
+    }
+
+    fn process_deposit(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+    ) -> ProgramResult {
+        let DepositAccounts {
+            escrow: escrow_info,
+            escrow_bump,
+            vault: vault_info,
+            vault_bump,
+            voter: voter_info,
+            source: source_info,
+            mint: mint_info,
+            system_program: system_program_info,
+            token_program: token_program_info,
+        } = DepositAccounts::try_from((program_id, accounts))?;
+
+        let mut escrow = if escrow_info.owner == program_id {
+            load_escrow(program_id, escrow_info)?
+        } else {
+            create_pda_account(
+                voter_info,
+                escrow_info,
+                system_program_info,
+                program_id,
+                VoterEscrow::LEN,
+                &[
+                    ESCROW_SEED,
+                    mint_info.key.as_ref(),
+                    voter_info.key.as_ref(),
+                    &[escrow_bump],
+                ],
+            )?;
+            create_pda_account(
+                voter_info,
+                vault_info,
+                system_program_info,
+                &spl_token::id(),
+                spl_token::state::Account::LEN,
+                &[
+                    ESCROW_VAULT_SEED,
+                    mint_info.key.as_ref(),
+                    voter_info.key.as_ref(),
+                    &[vault_bump],
+                ],
+            )?;
+            invoke(
+                &spl_token::instruction::initialize_account3(
+                    &spl_token::id(),
+                    vault_info.key,
+                    mint_info.key,
+                    escrow_info.key,
+                )?,
+                &[vault_info.clone(), mint_info.clone()],
+            )?;
+
+            VoterEscrow {
+                account_type: AccountType::VoterEscrow,
+                mint: *mint_info.key,
+                voter: *voter_info.key,
+                amount: 0,
+                active_votes: 0,
+                active_proposals: 0,
+            }
+        };
+
+        invoke(
+            &spl_token::instruction::transfer(
+                &spl_token::id(),
+                source_info.key,
+                vault_info.key,
+                voter_info.key,
+                &[],
+                amount,
+            )?,
+            &[
+                source_info.clone(),
+                vault_info.clone(),
+                voter_info.clone(),
+                token_program_info.clone(),
+            ],
+        )?;
+
+        escrow.amount = escrow
+            .amount
+            .checked_add(amount)
+            .ok_or(VoteError::CounterOverflow)?;
+        escrow.serialize(&mut &mut escrow_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_withdraw(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        amount: u64,
+    ) -> ProgramResult {
+        let WithdrawAccounts {
+            escrow: escrow_info,
+            vault: vault_info,
+            voter: voter_info,
+            destination: destination_info,
+            token_program: token_program_info,
+        } = WithdrawAccounts::try_from((program_id, accounts))?;
+
+        let mut escrow = load_escrow(program_id, escrow_info)?;
+        let (escrow_address, bump) = find_escrow_address(program_id, &escrow.mint, voter_info.key);
+        if escrow_address != *escrow_info.key {
+            return Err(VoteError::InvalidVoterEscrow.into());
+        }
+        let (vault_address, _) =
+            find_escrow_vault_address(program_id, &escrow.mint, voter_info.key);
+        check_address(vault_info, &vault_address)?;
+
+        if escrow.active_votes > 0 {
+            msg!("{} votes must be relinquished first", escrow.active_votes);
+            return Err(VoteError::EscrowLocked.into());
+        }
+        if escrow.active_proposals > 0 {
+            msg!(
+                "{} proposals must be resolved first",
+                escrow.active_proposals
+            );
+            return Err(VoteError::EscrowLocked.into());
+        }
+        escrow.amount = escrow
+            .amount
+            .checked_sub(amount)
+            .ok_or(VoteError::InsufficientEscrow)?;
+
+        invoke_signed(
+            &spl_token::instruction::transfer(
+                &spl_token::id(),
+                vault_info.key,
+                destination_info.key,
+                escrow_info.key,
+                &[],
+                amount,
+            )?,
+            &[
+                vault_info.clone(),
+                destination_info.clone(),
+                escrow_info.clone(),
+                token_program_info.clone(),
+            ],
+            &[&[
+                ESCROW_SEED,
+                escrow.mint.as_ref(),
+                voter_info.key.as_ref(),
+                &[bump],
+            ]],
+        )?;
+
+        escrow.serialize(&mut &mut escrow_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_relinquish_vote(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let RelinquishVoteAccounts {
+            poll: poll_info,
+            voter: voter_info,
+            receipt: receipt_info,
+            escrow: escrow_info,
+        } = RelinquishVoteAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        let mint = match &poll.vote_weight {
+            VoteWeight::Escrow { mint } => *mint,
+            _ => return Err(VoteError::NotEscrowVote.into()),
+        };
+
+        let receipt = load_receipt(program_id, receipt_info)?;
+
+        let mut escrow = load_escrow(program_id, escrow_info)?;
+        check_escrow(&escrow, &mint, voter_info.key)?;
+
+        // Once voting is over the tally stands, only the tokens are released
+        let voting_open = !poll.is_closed && Clock::get()?.unix_timestamp < poll.tally_end_ts();
+        if voting_open {
+            if receipt.delegator_count > 0 {
+                return Err(VoteError::DelegatorsOutstanding.into());
+            }
+            remove_vote(&mut poll, &receipt)?;
+        }
+        poll.open_receipts = poll.open_receipts.saturating_sub(1);
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        escrow.active_votes = escrow.active_votes.saturating_sub(1);
+        escrow.serialize(&mut &mut escrow_info.data.borrow_mut()[..])?;
+
+        close_account(receipt_info, voter_info)
+    }
+
+    fn process_change_vote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        option: u8,
+    ) -> ProgramResult {
+        let ChangeVoteAccounts {
+            poll: poll_info,
+            voter: _,
+            receipt: receipt_info,
+        } = ChangeVoteAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        check_voting_open(&poll)?;
+        check_single_choice(&poll)?;
+
+        let mut receipt = load_receipt(program_id, receipt_info)?;
+        if receipt.delegate.is_some() {
+            return Err(VoteError::VoteDelegated.into());
+        }
+        if option as usize >= poll.options.len() {
+            return Err(VoteError::UnknownVoteOption.into());
+        }
+
+        // The vote keeps the weight it was cast with, it only moves between options
+        poll.options[receipt.option as usize].remove_votes(receipt.weight)?;
+        poll.options[option as usize].add_votes(receipt.weight)?;
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        receipt.option = option;
+        receipt.selections = 1 << option;
+        receipt.serialize(&mut &mut receipt_info.data.borrow_mut()[..])?;
+
+        for vote_option in &poll.options {
+            msg!("Votes: {}: {}", vote_option.label, vote_option.votes);
+        }
+
+        Ok(())
+    }
+
+    fn process_reveal_vote(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        option: u8,
+        salt: &[u8; 32],
+    ) -> ProgramResult {
+        let RevealVoteAccounts {
+            poll: poll_info,
+            voter: voter_info,
+            receipt: receipt_info,
+        } = RevealVoteAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        let reveal_end_ts = poll.reveal_end_ts.ok_or(VoteError::UnsupportedBallotType)?;
+        check_state(&poll, ProposalState::Voting)?;
+        let now = Clock::get()?.unix_timestamp;
+        if poll.is_closed || now < poll.end_ts || now >= reveal_end_ts {
+            return Err(VoteError::RevealNotOpen.into());
+        }
+
+        let mut receipt = load_receipt(program_id, receipt_info)?;
+        let commitment = receipt.commitment.ok_or(VoteError::NotCommitted)?;
+        if vote_commitment(option, salt, voter_info.key) != commitment {
+            return Err(VoteError::InvalidReveal.into());
+        }
+
+        receipt.selections = select(&poll, 0, option)?;
+        receipt.option = option;
+        receipt.commitment = None;
+        poll.options[option as usize].add_votes(receipt.weight)?;
+        poll.participating_weight = poll
+            .participating_weight
+            .checked_add(receipt.weight)
+            .ok_or(VoteError::CounterOverflow)?;
+        poll.unrevealed_commits = poll.unrevealed_commits.saturating_sub(1);
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+        receipt.serialize(&mut &mut receipt_info.data.borrow_mut()[..])?;
+
+        for vote_option in &poll.options {
+            msg!("Votes: {}: {}", vote_option.label, vote_option.votes);
+        }
+
+        Ok(())
+    }
+
+    fn process_retract_vote(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let RetractVoteAccounts {
+            poll: poll_info,
+            voter: voter_info,
+            receipt: receipt_info,
+            remaining,
+        } = RetractVoteAccounts::try_from((program_id, accounts))?;
+        let remaining = &mut remaining.iter();
+
+        let mut poll = load_poll(poll_info)?;
+        check_voting_open(&poll)?;
+
+        let receipt = load_receipt(program_id, receipt_info)?;
+        remove_vote(&mut poll, &receipt)?;
+        poll.open_receipts = poll.open_receipts.saturating_sub(1);
+
+        // The retracted vote no longer locks the escrowed tokens
+        if let VoteWeight::Escrow { mint } = &poll.vote_weight {
+            release_escrow(
+                program_id,
+                next_account_info(remaining)?,
+                mint,
+                voter_info.key,
+            )?;
+        }
+
+        // Delegated weight leaves the tally with the delegate's vote, so the delegators are
+        // free to vote on their own again. The delegate paid for their receipts.
+        for _ in 0..receipt.delegator_count {
+            let delegator_receipt_info = next_account_info(remaining)?;
+            check_writable(delegator_receipt_info)?;
+            let delegator_receipt = load_receipt(program_id, delegator_receipt_info)?;
+            if delegator_receipt.poll != *poll_info.key
+                || delegator_receipt.delegate != Some(*voter_info.key)
+            {
+                return Err(VoteError::InvalidDelegation.into());
+            }
+            if let VoteWeight::Escrow { mint } = &poll.vote_weight {
+                release_escrow(
+                    program_id,
+                    next_account_info(remaining)?,
+                    mint,
+                    &delegator_receipt.voter,
+                )?;
+            }
+            poll.open_receipts = poll.open_receipts.saturating_sub(1);
+            close_account(delegator_receipt_info, voter_info)?;
+        }
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        close_account(receipt_info, voter_info)
+    }
+
+    fn process_initialize_registry(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        registry_id: u64,
+    ) -> ProgramResult {
+        let InitializeRegistryAccounts {
+            registry: registry_info,
+            authority: authority_info,
+            system_program: system_program_info,
+        } = InitializeRegistryAccounts::try_from((program_id, accounts))?;
+
+        let (registry_address, bump) =
+            find_registry_address(program_id, authority_info.key, registry_id);
+        check_address(registry_info, &registry_address)?;
+        if registry_info.owner == program_id {
+            msg!("Voter registry is already in use");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        create_pda_account(
+            authority_info,
+            registry_info,
+            system_program_info,
+            program_id,
+            VoterRegistry::LEN,
+            &[
+                REGISTRY_SEED,
+                authority_info.key.as_ref(),
+                &registry_id.to_le_bytes(),
+                &[bump],
+            ],
+        )?;
+        let registry = VoterRegistry {
+            account_type: AccountType::VoterRegistry,
+            authority: *authority_info.key,
+            registry_id,
+            member_count: 0,
+        };
+        registry.serialize(&mut &mut registry_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_add_member(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        member: &Pubkey,
+    ) -> ProgramResult {
+        let AddMemberAccounts {
+            registry: registry_info,
+            authority: authority_info,
+            membership: membership_info,
+            system_program: system_program_info,
+        } = AddMemberAccounts::try_from((program_id, accounts))?;
+
+        let mut registry = load_registry(program_id, registry_info)?;
+        if registry.authority != *authority_info.key {
+            return Err(VoteError::InvalidAuthority.into());
+        }
+
+        let (membership_address, bump) = find_member_address(program_id, registry_info.key, member);
+        check_address(membership_info, &membership_address)?;
+        if membership_info.owner == program_id {
+            return Err(VoteError::AlreadyRegistryMember.into());
+        }
+
+        create_pda_account(
+            authority_info,
+            membership_info,
+            system_program_info,
+            program_id,
+            RegistryMember::LEN,
+            &[
+                MEMBER_SEED,
+                registry_info.key.as_ref(),
+                member.as_ref(),
+                &[bump],
+            ],
+        )?;
+        let membership = RegistryMember {
+            account_type: AccountType::RegistryMember,
+            registry: *registry_info.key,
+            member: *member,
+        };
+        membership.serialize(&mut &mut membership_info.data.borrow_mut()[..])?;
+
+        registry.member_count = registry
+            .member_count
+            .checked_add(1)
+            .ok_or(VoteError::CounterOverflow)?;
+        registry.serialize(&mut &mut registry_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_remove_member(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        member: &Pubkey,
+    ) -> ProgramResult {
+        let RemoveMemberAccounts {
+            registry: registry_info,
+            authority: authority_info,
+            membership: membership_info,
+        } = RemoveMemberAccounts::try_from((program_id, accounts))?;
+
+        let mut registry = load_registry(program_id, registry_info)?;
+        if registry.authority != *authority_info.key {
+            return Err(VoteError::InvalidAuthority.into());
+        }
+
+        let (membership_address, _) = find_member_address(program_id, registry_info.key, member);
+        check_address(membership_info, &membership_address)?;
+        if membership_info.owner != program_id {
+            return Err(VoteError::NotRegistryMember.into());
+        }
+
+        close_account(membership_info, authority_info)?;
+
+        registry.member_count = registry.member_count.saturating_sub(1);
+        registry.serialize(&mut &mut registry_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_close_poll_account(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let ClosePollAccountAccounts {
+            poll: poll_info,
+            authority: authority_info,
+            recipient: recipient_info,
+            ranked_tally: ranked_tally_info,
+        } = ClosePollAccountAccounts::try_from((program_id, accounts))?;
+
+        let poll = load_poll(poll_info)?;
+        check_authority(&poll, authority_info)?;
+        if !poll.is_finalized {
+            return Err(VoteError::NotFinalized.into());
+        }
+        // Succeeded proposals still have transactions to execute
+        if !matches!(
+            poll.state,
+            ProposalState::Defeated | ProposalState::Executed | ProposalState::Cancelled
+        ) {
+            return Err(VoteError::InvalidProposalState.into());
+        }
+        // Once the account is gone the address can be re-created, receipts left behind
+        // would then count as votes in the new poll
+        if poll.open_receipts > 0 {
+            msg!("{} vote receipts are still open", poll.open_receipts);
+            return Err(VoteError::ReceiptsOutstanding.into());
+        }
+        if poll.transaction_count > 0 {
+            msg!(
+                "{} proposal transactions are still open",
+                poll.transaction_count
+            );
+            return Err(VoteError::TransactionsOutstanding.into());
+        }
+        if poll.ballot_type == BallotType::RankedChoice {
+            let ranked_tally_info = ranked_tally_info.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            let (ranked_tally_address, _) = find_ranked_tally_address(program_id, poll_info.key);
+            check_address(ranked_tally_info, &ranked_tally_address)?;
+            if poll.ballot_count > 0 || ranked_tally_info.owner == program_id {
+                msg!("{} ranked ballots are still stored", poll.ballot_count);
+                return Err(VoteError::BallotsOutstanding.into());
+            }
+        }
+
+        let lamports = recipient_info
+            .lamports()
+            .checked_add(poll_info.lamports())
+            .ok_or(VoteError::CounterOverflow)?;
+        **recipient_info.try_borrow_mut_lamports()? = lamports;
+        **poll_info.try_borrow_mut_lamports()? = 0;
+
+        // The tombstone stays owned by the program, so the address can't be revived with
+        // lamports sent to it later in the same transaction
+        let mut data = poll_info.data.borrow_mut();
+        data.fill(0);
+        data[0] = AccountType::Closed as u8;
+
+        Ok(())
+    }
+
+    fn process_close_receipt(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let CloseReceiptAccounts {
+            poll: poll_info,
+            receipt: receipt_info,
+            voter: voter_info,
+            escrow: escrow_info,
+        } = CloseReceiptAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        if !poll.is_finalized {
+            return Err(VoteError::NotFinalized.into());
+        }
+        load_receipt(program_id, receipt_info)?;
+
+        // The tally is final, so the tokens backing the vote are released with the receipt
+        if let VoteWeight::Escrow { mint } = &poll.vote_weight {
+            let escrow_info = escrow_info.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            release_escrow(program_id, escrow_info, mint, voter_info.key)?;
+        }
+
+        poll.open_receipts = poll.open_receipts.saturating_sub(1);
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        close_account(receipt_info, voter_info)
+    }
+
+    fn process_insert_transaction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        index: u16,
+        hold_up_time: u32,
+        instructions: Vec<InstructionData>,
+    ) -> ProgramResult {
+        let InsertTransactionAccounts {
+            poll: poll_info,
+            authority: authority_info,
+            proposal_transaction: proposal_transaction_info,
+            system_program: system_program_info,
+        } = InsertTransactionAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        check_authority(&poll, authority_info)?;
+        // Drafts have no voting window yet, voting polls only until their window opens
+        let editable = match poll.state {
+            ProposalState::Draft => true,
+            ProposalState::Voting => Clock::get()?.unix_timestamp < poll.start_ts,
+            _ => false,
+        };
+        if !editable {
+            return Err(VoteError::VotingStarted.into());
+        }
+        if instructions.is_empty() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        let (proposal_transaction_address, bump) =
+            find_proposal_transaction_address(program_id, poll_info.key, index);
+        check_address(proposal_transaction_info, &proposal_transaction_address)?;
+        if proposal_transaction_info.owner == program_id {
+            msg!("Proposal transaction is already in use");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let proposal_transaction = ProposalTransaction {
+            account_type: AccountType::ProposalTransaction,
+            poll: *poll_info.key,
+            index,
+            hold_up_time,
+            instructions,
+            executed_at: None,
+        };
+        // Leave room for the execution timestamp
+        let space = get_instance_packed_len(&proposal_transaction)? + 8;
+        create_pda_account(
+            authority_info,
+            proposal_transaction_info,
+            system_program_info,
+            program_id,
+            space,
+            &[
+                PROPOSAL_TRANSACTION_SEED,
+                poll_info.key.as_ref(),
+                &index.to_le_bytes(),
+                &[bump],
+            ],
+        )?;
+        proposal_transaction
+            .serialize(&mut &mut proposal_transaction_info.data.borrow_mut()[..])?;
+
+        poll.transaction_count = poll
+            .transaction_count
+            .checked_add(1)
+            .ok_or(VoteError::CounterOverflow)?;
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_execute_proposal(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let ExecuteProposalAccounts {
+            poll: poll_info,
+            proposal_transaction: proposal_transaction_info,
+            treasury: treasury_info,
+        } = ExecuteProposalAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        let mut proposal_transaction = load_proposal_transaction(proposal_transaction_info)?;
+        if proposal_transaction.poll != *poll_info.key {
+            msg!("Proposal transaction belongs to another poll");
+            return Err(ProgramError::InvalidArgument);
+        }
+        if proposal_transaction.executed_at.is_some() {
+            return Err(VoteError::AlreadyExecuted.into());
+        }
+        if poll.state != ProposalState::Succeeded {
+            return Err(VoteError::ProposalNotPassed.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        if now < proposal_transaction.executable_at(&poll) {
+            return Err(VoteError::HoldUpTimeNotElapsed.into());
+        }
+        if now >= proposal_transaction.expires_at(&poll) {
+            return Err(VoteError::TransactionExpired.into());
+        }
+
+        let (treasury_address, bump) = find_treasury_address(program_id, poll.governance());
+        check_address(treasury_info, &treasury_address)?;
+
+        // Mark the transaction executed first, the instructions may call back into the program
+        proposal_transaction.executed_at = Some(now);
+        proposal_transaction
+            .serialize(&mut &mut proposal_transaction_info.data.borrow_mut()[..])?;
+        poll.executed_transaction_count = poll.executed_transaction_count.saturating_add(1);
+        if poll.executed_transaction_count >= poll.transaction_count {
+            poll.state = ProposalState::Executed;
+        }
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        for instruction in &proposal_transaction.instructions {
+            invoke_signed(
+                &instruction.into(),
+                accounts,
+                &[&[TREASURY_SEED, poll.governance().as_ref(), &[bump]]],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn process_close_proposal_transaction(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+    ) -> ProgramResult {
+        let CloseProposalTransactionAccounts {
+            poll: poll_info,
+            proposal_transaction: proposal_transaction_info,
+            authority: authority_info,
+        } = CloseProposalTransactionAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        let proposal_transaction = load_proposal_transaction(proposal_transaction_info)?;
+        if proposal_transaction.poll != *poll_info.key {
+            msg!("Proposal transaction belongs to another poll");
+            return Err(ProgramError::InvalidArgument);
+        }
+        // The authority paid for the transaction when inserting it
+        if *authority_info.key != poll.authority {
+            return Err(VoteError::InvalidAuthority.into());
+        }
+
+        // Transactions of polls that can no longer pass will never run, nor will expired ones.
+        // Executed ones stay until the poll is done executing, so its count of them holds.
+        let executed = proposal_transaction.executed_at.is_some();
+        let closable = match poll.state {
+            ProposalState::Defeated | ProposalState::Cancelled | ProposalState::Executed => true,
+            ProposalState::Succeeded => {
+                !executed && Clock::get()?.unix_timestamp >= proposal_transaction.expires_at(&poll)
+            }
+            _ => false,
+        };
+        if !closable {
+            return Err(VoteError::InvalidProposalState.into());
+        }
+
+        poll.transaction_count = poll.transaction_count.saturating_sub(1);
+        if executed {
+            poll.executed_transaction_count = poll.executed_transaction_count.saturating_sub(1);
+        }
+        if poll.state == ProposalState::Succeeded
+            && poll.executed_transaction_count >= poll.transaction_count
+        {
+            poll.state = if poll.executed_transaction_count > 0 {
+                ProposalState::Executed
+            } else {
+                ProposalState::Defeated
+            };
+            msg!("State: {:?}", poll.state);
+        }
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        close_account(proposal_transaction_info, authority_info)
+    }
+
+    fn process_close_ballots(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let CloseBallotsAccounts {
+            poll: poll_info,
+            authority: authority_info,
+            recipient: recipient_info,
+            ranked_tally: ranked_tally_info,
+            ballot_chunks,
+        } = CloseBallotsAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        check_authority(&poll, authority_info)?;
+        if poll.ballot_type != BallotType::RankedChoice {
+            return Err(VoteError::UnsupportedBallotType.into());
+        }
+        if !poll.is_finalized {
+            return Err(VoteError::NotFinalized.into());
+        }
+
+        let (ranked_tally_address, _) = find_ranked_tally_address(program_id, poll_info.key);
+        check_address(ranked_tally_info, &ranked_tally_address)?;
+        // Cancelled polls may never have been tallied
+        if ranked_tally_info.owner == program_id {
+            load_ranked_tally(ranked_tally_info)?;
+            close_account(ranked_tally_info, recipient_info)?;
+        }
+
+        // Closing the last chunk first keeps the ballot count pointing past the open ones
+        for ballot_chunk_info in ballot_chunks {
+            if poll.ballot_count == 0 {
+                break;
+            }
+            let index = (poll.ballot_count - 1) / BALLOTS_PER_CHUNK;
+            let (ballot_chunk_address, _) =
+                find_ballot_chunk_address(program_id, poll_info.key, index);
+            check_address(ballot_chunk_info, &ballot_chunk_address)?;
+            check_owner(ballot_chunk_info, program_id)?;
+            load_ballot_chunk(ballot_chunk_info)?;
+            close_account(ballot_chunk_info, recipient_info)?;
+            poll.ballot_count = index * BALLOTS_PER_CHUNK;
+        }
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_tally(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let TallyAccounts {
+            poll: poll_info,
+            ranked_tally: ranked_tally_info,
+            payer: payer_info,
+            system_program: system_program_info,
+            ballot_chunks,
+        } = TallyAccounts::try_from((program_id, accounts))?;
+
+        let poll = load_poll(poll_info)?;
+        if poll.ballot_type != BallotType::RankedChoice {
+            return Err(VoteError::UnsupportedBallotType.into());
+        }
+        if matches!(poll.state, ProposalState::Draft | ProposalState::Cancelled) {
+            return Err(VoteError::InvalidProposalState.into());
+        }
+        // Finalizing takes a complete tally, whose accounts may be closed since
+        if poll.is_finalized {
+            return Err(VoteError::AlreadyFinalized.into());
+        }
+        // Ballots can only be counted once no more can be added
+        if !poll.is_closed && Clock::get()?.unix_timestamp < poll.end_ts {
+            return Err(VoteError::VotingNotEnded.into());
+        }
+
+        let (ranked_tally_address, bump) = find_ranked_tally_address(program_id, poll_info.key);
+        check_address(ranked_tally_info, &ranked_tally_address)?;
+        let mut ranked_tally = if ranked_tally_info.owner == program_id {
+            load_ranked_tally(ranked_tally_info)?
+        } else {
+            create_pda_account(
+                payer_info,
+                ranked_tally_info,
+                system_program_info,
+                program_id,
+                RankedTally::space(poll.options.len()),
+                &[RANKED_TALLY_SEED, poll_info.key.as_ref(), &[bump]],
+            )?;
+            RankedTally::new(*poll_info.key, poll.options.len())
+        };
+        if ranked_tally.is_complete {
+            return Err(VoteError::TallyComplete.into());
+        }
+
+        let chunk_count = poll.ballot_count.div_ceil(BALLOTS_PER_CHUNK);
+        let mut ballot_chunks = ballot_chunks.iter();
+        while !ranked_tally.is_complete {
+            if ranked_tally.next_chunk == chunk_count {
+                ranked_tally.complete_round();
+                msg!(
+                    "Round {}: {:?}",
+                    ranked_tally.rounds.len(),
+                    ranked_tally.rounds.last()
+                );
+                continue;
+            }
+
+            // Out of ballot chunks, the next transaction continues from here
+            let Some(ballot_chunk_info) = ballot_chunks.next() else {
+                break;
+            };
+            let (ballot_chunk_address, _) =
+                find_ballot_chunk_address(program_id, poll_info.key, ranked_tally.next_chunk);
+            check_address(ballot_chunk_info, &ballot_chunk_address)?;
+            let ballot_chunk = load_ballot_chunk(ballot_chunk_info)?;
+            for ballot in &ballot_chunk.ballots {
+                ranked_tally.count_ballot(ballot)?;
+            }
+            ranked_tally.next_chunk += 1;
+        }
+
+        ranked_tally.serialize(&mut &mut ranked_tally_info.data.borrow_mut()[..])?;
+
+        if let Some(winner) = ranked_tally.winner {
+            msg!("Winner: {}", poll.options[winner as usize].label);
+        }
+
+        Ok(())
+    }
+
+    fn process_delegate(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        delegate: &Pubkey,
+        scope: DelegationScope,
+    ) -> ProgramResult {
+        let DelegateAccounts {
+            delegator: delegator_info,
+            delegation: delegation_info,
+            system_program: system_program_info,
+        } = DelegateAccounts::try_from((program_id, accounts))?;
+
+        if delegate == delegator_info.key {
+            return Err(VoteError::InvalidDelegation.into());
+        }
+
+        let (delegation_address, bump) =
+            find_delegation_address(program_id, delegator_info.key, &scope);
+        check_address(delegation_info, &delegation_address)?;
+
+        // Delegating again only moves the weight to the new delegate
+        if delegation_info.owner == program_id {
+            let mut delegation = load_delegation(program_id, delegation_info)?;
+            delegation.delegate = *delegate;
+            delegation.serialize(&mut &mut delegation_info.data.borrow_mut()[..])?;
+            return Ok(());
+        }
+
+        create_pda_account(
+            delegator_info,
+            delegation_info,
+            system_program_info,
+            program_id,
+            Delegation::LEN,
+            &[
+                DELEGATION_SEED,
+                delegator_info.key.as_ref(),
+                scope.seed(),
+                &[bump],
+            ],
+        )?;
+        let delegation = Delegation {
+            account_type: AccountType::Delegation,
+            delegator: *delegator_info.key,
+            delegate: *delegate,
+            scope,
+        };
+        delegation.serialize(&mut &mut delegation_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_revoke_delegation(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let RevokeDelegationAccounts {
+            delegator: delegator_info,
+            delegation: delegation_info,
+        } = RevokeDelegationAccounts::try_from((program_id, accounts))?;
+
+        let delegation = load_delegation(program_id, delegation_info)?;
+        if delegation.delegator != *delegator_info.key {
+            return Err(VoteError::InvalidAuthority.into());
+        }
+
+        close_account(delegation_info, delegator_info)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn process_create_realm(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        name: String,
+        governance_mint: Pubkey,
+        voting_duration: u32,
+        quorum: Quorum,
+        threshold: Threshold,
+        min_proposal_weight: u64,
+        proposal_deposit: u64,
+    ) -> ProgramResult {
+        let CreateRealmAccounts {
+            realm: realm_info,
+            authority: authority_info,
+            system_program: system_program_info,
+        } = CreateRealmAccounts::try_from((program_id, accounts))?;
+
+        if name.is_empty() || name.len() > MAX_REALM_NAME_LEN {
+            return Err(VoteError::InvalidRealmName.into());
+        }
+        if voting_duration == 0 {
+            return Err(VoteError::InvalidVotingWindow.into());
+        }
+        if let Quorum::Percent { percent } = quorum {
+            if percent == 0 || percent > 100 {
+                return Err(VoteError::InvalidQuorum.into());
+            }
+        }
+
+        let (realm_address, bump) = find_realm_address(program_id, &name);
+        check_address(realm_info, &realm_address)?;
+        if realm_info.owner == program_id {
+            msg!("Realm account is already in use");
+            return Err(ProgramError::AccountAlreadyInitialized);
+        }
+
+        let realm = Realm {
+            account_type: AccountType::Realm,
+            name,
+            authority: *authority_info.key,
+            governance_mint,
+            voting_duration,
+            quorum,
+            threshold,
+            min_proposal_weight,
+            proposal_deposit,
+            proposal_count: 0,
+        };
+        create_pda_account(
+            authority_info,
+            realm_info,
+            system_program_info,
+            program_id,
+            get_instance_packed_len(&realm)?,
+            &[REALM_SEED, realm.name.as_bytes(), &[bump]],
+        )?;
+        realm.serialize(&mut &mut realm_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn process_create_proposal(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        title: String,
+        description_uri: String,
+        content_hash: [u8; 32],
+        options: Vec<String>,
+        start_ts: i64,
+        ballot_type: BallotType,
+    ) -> ProgramResult {
+        let CreateProposalAccounts {
+            realm: realm_info,
+            poll: poll_info,
+            creator: creator_info,
+            system_program: system_program_info,
+            creator_escrow: creator_escrow_info,
+        } = CreateProposalAccounts::try_from((program_id, accounts))?;
+
+        let mut realm = load_realm(program_id, realm_info)?;
+
+        // The escrow stays locked until the proposal is resolved, so the same tokens can't
+        // be withdrawn and escrowed again to back another creator
+        let creator_escrow = if realm.min_proposal_weight > 0 {
+            let creator_escrow_info =
+                creator_escrow_info.ok_or(ProgramError::NotEnoughAccountKeys)?;
+            check_writable(creator_escrow_info)?;
+            let mut creator_escrow = load_escrow(program_id, creator_escrow_info)?;
+            check_escrow(&creator_escrow, &realm.governance_mint, creator_info.key)?;
+            if creator_escrow.amount < realm.min_proposal_weight {
+                return Err(VoteError::InsufficientProposalWeight.into());
+            }
+            creator_escrow.active_proposals = creator_escrow
+                .active_proposals
+                .checked_add(1)
+                .ok_or(VoteError::CounterOverflow)?;
+            creator_escrow.serialize(&mut &mut creator_escrow_info.data.borrow_mut()[..])?;
+            Some(*creator_escrow_info.key)
+        } else {
+            None
+        };
+
+        let poll = Poll {
+            account_type: AccountType::Poll,
+            authority: *creator_info.key,
+            poll_id: realm.proposal_count,
+            title,
+            description_uri,
+            content_hash,
+            options: options
+                .into_iter()
+                .map(|label| VoteOption { label, votes: 0 })
+                .collect(),
+            start_ts,
+            end_ts: start_ts.saturating_add(realm.voting_duration.into()),
+            vote_weight: VoteWeight::Escrow {
+                mint: realm.governance_mint,
+            },
+            voter_registry: None,
+            open_receipts: 0,
+            participating_weight: 0,
+            quorum: realm.quorum.clone(),
+            threshold: realm.threshold,
+            outcome: Outcome::Pending,
+            state: ProposalState::Draft,
+            ballot_type,
+            ballot_count: 0,
+            reveal_end_ts: None,
+            unrevealed_commits: 0,
+            realm: Some(*realm_info.key),
+            deposit: realm.proposal_deposit,
+            creator_escrow,
+            transaction_count: 0,
+            executed_transaction_count: 0,
+            is_closed: false,
+            is_finalized: false,
+        };
+        check_poll_config(&poll)?;
+
+        // Nobody can sign for the realm, so its proposals never collide with standalone polls
+        let (poll_address, bump) = find_poll_address(program_id, realm_info.key, poll.poll_id);
+        check_address(poll_info, &poll_address)?;
+
+        create_pda_account(
+            creator_info,
+            poll_info,
+            system_program_info,
+            program_id,
+            get_instance_packed_len(&poll)?,
+            &[
+                POLL_SEED,
+                realm_info.key.as_ref(),
+                &poll.poll_id.to_le_bytes(),
+                &[bump],
+            ],
+        )?;
+        check_rent_exempt(poll_info)?;
+        if poll.deposit > 0 {
+            invoke(
+                &system_instruction::transfer(creator_info.key, poll_info.key, poll.deposit),
+                &[
+                    creator_info.clone(),
+                    poll_info.clone(),
+                    system_program_info.clone(),
+                ],
+            )?;
+        }
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        realm.proposal_count = realm
+            .proposal_count
+            .checked_add(1)
+            .ok_or(VoteError::CounterOverflow)?;
+        realm.serialize(&mut &mut realm_info.data.borrow_mut()[..])?;
+
+        Ok(())
+    }
+
+    fn process_cancel_proposal(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let CancelProposalAccounts {
+            poll: poll_info,
+            signer: signer_info,
+            remaining,
+        } = CancelProposalAccounts::try_from((program_id, accounts))?;
+        let remaining = &mut remaining.iter();
+
+        let mut poll = load_poll(poll_info)?;
+        if poll.is_finalized {
+            return Err(VoteError::AlreadyFinalized.into());
+        }
+        match poll.state {
+            // Drafts never opened, so they can be withdrawn at any time
+            ProposalState::Draft => {}
+            ProposalState::Voting => {
+                if Clock::get()?.unix_timestamp >= poll.tally_end_ts() {
+                    return Err(VoteError::Ended.into());
+                }
+            }
+            _ => return Err(VoteError::InvalidProposalState.into()),
+        }
+
+        if poll.authority == *signer_info.key {
+            if poll.deposit > 0 {
+                transfer_lamports(poll_info, signer_info, poll.deposit)?;
+            }
+        } else {
+            // Anyone else's proposal can only be cancelled by the realm authority, as spam
+            let realm_address = poll.realm.ok_or(VoteError::InvalidAuthority)?;
+            let realm_info = next_account_info(remaining)?;
+            check_address(realm_info, &realm_address)?;
+            let realm = load_realm(program_id, realm_info)?;
+            if realm.authority != *signer_info.key {
+                return Err(VoteError::InvalidAuthority.into());
+            }
+            let treasury_info = next_account_info(remaining)?;
+            let (treasury_address, _) = find_treasury_address(program_id, &realm_address);
+            check_address(treasury_info, &treasury_address)?;
+            if poll.deposit > 0 {
+                check_writable(treasury_info)?;
+                transfer_lamports(poll_info, treasury_info, poll.deposit)?;
+            }
+        }
+        unlock_creator_escrow(program_id, &poll, remaining)?;
+
+        poll.deposit = 0;
+        poll.cancel();
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        msg!("Outcome: {:?}", poll.outcome);
+
+        Ok(())
+    }
+
+    fn process_sign_off(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+        let SignOffAccounts {
+            poll: poll_info,
+            authority: authority_info,
+        } = SignOffAccounts::try_from((program_id, accounts))?;
+
+        let mut poll = load_poll(poll_info)?;
+        check_authority(&poll, authority_info)?;
+        check_state(&poll, ProposalState::Draft)?;
+
+        // A late sign-off must not cut the voting window short
+        let delay = Clock::get()?.unix_timestamp.saturating_sub(poll.start_ts);
+        if delay > 0 {
+            poll.start_ts = poll.start_ts.saturating_add(delay);
+            poll.end_ts = poll.end_ts.saturating_add(delay);
+            poll.reveal_end_ts = poll.reveal_end_ts.map(|ts| ts.saturating_add(delay));
+        }
+
+        poll.state = ProposalState::Voting;
+        poll.serialize(&mut &mut poll_info.data.borrow_mut()[..])?;
+
+        msg!("Voting: {} to {}", poll.start_ts, poll.end_ts);
+
+        Ok(())
+    }
+}
+
+/// Rejects poll configurations the program can't run.
+fn check_poll_config(poll: &Poll) -> ProgramResult {
+    if poll.title.len() > MAX_TITLE_LEN {
+        return Err(VoteError::TitleTooLong.into());
+    }
+    if poll.description_uri.len() > MAX_DESCRIPTION_URI_LEN {
+        return Err(VoteError::DescriptionUriTooLong.into());
+    }
+    if poll.options.len() < MIN_OPTIONS || poll.options.len() > MAX_OPTIONS {
+        return Err(VoteError::InvalidOptionCount.into());
+    }
+    if poll
+        .options
+        .iter()
+        .any(|option| option.label.len() > MAX_OPTION_LABEL_LEN)
+    {
+        return Err(VoteError::OptionLabelTooLong.into());
+    }
+
+    if poll.end_ts <= poll.start_ts {
+        return Err(VoteError::InvalidVotingWindow.into());
+    }
+
+    // Proofs are only accepted with single choice ballots
+    if poll.ballot_type != BallotType::SingleChoice
+        && matches!(poll.vote_weight, VoteWeight::MerkleAllowlist { .. })
+    {
+        return Err(VoteError::UnsupportedBallotType.into());
+    }
+    if let BallotType::Approval {
+        min_selections,
+        max_selections,
+    } = poll.ballot_type
+    {
+        if min_selections == 0
+            || min_selections > max_selections
+            || max_selections as usize > poll.options.len()
+        {
+            return Err(VoteError::InvalidSelectionCount.into());
+        }
+    }
+    // The instant-runoff winner always holds a simple majority of the final round
+    if poll.ballot_type == BallotType::RankedChoice && poll.threshold != Threshold::SimpleMajority {
+        return Err(VoteError::InvalidThreshold.into());
+    }
+    if poll.ballot_type == (BallotType::Quadratic { voice_credits: 0 }) {
+        msg!("Quadratic polls need voice credits");
+        return Err(ProgramError::InvalidArgument);
+    }
+    if let Some(reveal_end_ts) = poll.reveal_end_ts {
+        if reveal_end_ts <= poll.end_ts {
+            return Err(VoteError::InvalidVotingWindow.into());
+        }
+        // Secret votes are single choice and can't carry a proof
+        if poll.ballot_type != BallotType::SingleChoice
+            || matches!(poll.vote_weight, VoteWeight::MerkleAllowlist { .. })
+        {
+            return Err(VoteError::UnsupportedBallotType.into());
+        }
+    }
+
+    if let Quorum::Percent { percent } = poll.quorum {
+        // The total weight is only known for a governance mint or a registry
+        let has_total_weight = match poll.vote_weight {
+            VoteWeight::Token { .. } | VoteWeight::Escrow { .. } => true,
+            VoteWeight::OnePerWallet => poll.voter_registry.is_some(),
+            VoteWeight::MerkleAllowlist { .. } => false,
+        };
+        if percent == 0 || percent > 100 || !has_total_weight {
+            return Err(VoteError::InvalidQuorum.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// What a cast vote counts for.
+enum Ballot<'a> {
+    Single(u8),
+    Ranked(&'a [u8]),
+    Approval(&'a [u8]),
+    Quadratic(&'a [VoiceCreditAllocation]),
+    Commitment([u8; 32]),
+}
+
+/// Adds `option` to the bitmask of selected options.
+fn select(poll: &Poll, selections: u32, option: u8) -> Result<u32, ProgramError> {
+    if option as usize >= poll.options.len() {
+        return Err(VoteError::UnknownVoteOption.into());
+    }
+    if selections & (1 << option) != 0 {
+        return Err(VoteError::DuplicateSelection.into());
+    }
+
+    Ok(selections | 1 << option)
+}
+
+fn approval_selections(poll: &Poll, options: &[u8]) -> Result<u32, ProgramError> {
+    let (min_selections, max_selections) = match poll.ballot_type {
+        BallotType::Approval {
+            min_selections,
+            max_selections,
+        } => (min_selections as usize, max_selections as usize),
+        _ => return Err(VoteError::UnsupportedBallotType.into()),
+    };
+    if options.len() < min_selections || options.len() > max_selections {
+        return Err(VoteError::InvalidSelectionCount.into());
+    }
+
+    options
+        .iter()
+        .try_fold(0, |selections, option| select(poll, selections, *option))
+}
+
+/// Checks the allocations against the voter's voice credit budget, which grows with the
+/// weight of the vote.
+fn quadratic_selections(
+    poll: &Poll,
+    allocations: &[VoiceCreditAllocation],
+    weight: u64,
+) -> Result<u32, ProgramError> {
+    let budget = match poll.ballot_type {
+        // Capped rather than rejected, so the largest holders can still vote
+        BallotType::Quadratic { voice_credits } => voice_credits.saturating_mul(weight),
+        _ => return Err(VoteError::UnsupportedBallotType.into()),
+    };
+    if allocations.is_empty() {
+        return Err(VoteError::InvalidSelectionCount.into());
+    }
+    if allocations.iter().any(|allocation| allocation.credits == 0) {
+        return Err(VoteError::InvalidAllocation.into());
+    }
+
+    let spent = allocations
+        .iter()
+        .try_fold(0u64, |spent, allocation| {
+            spent.checked_add(allocation.credits)
+        })
+        .ok_or(VoteError::CounterOverflow)?;
+    if spent > budget {
+        return Err(VoteError::InsufficientVoiceCredits.into());
+    }
+
+    allocations.iter().try_fold(0, |selections, allocation| {
+        select(poll, selections, allocation.option)
+    })
+}
+
+/// Takes a vote out of the tally of every option it counts for.
+fn remove_vote(poll: &mut Poll, receipt: &VoteReceipt) -> ProgramResult {
+    // A stored ranked ballot can't be taken back
+    if poll.ballot_type == BallotType::RankedChoice {
+        return Err(VoteError::UnsupportedBallotType.into());
+    }
+    // The delegate's vote still counts the delegated weight
+    if receipt.delegate.is_some() {
+        return Err(VoteError::VoteDelegated.into());
+    }
+
+    for (option, votes) in receipt.tallied_votes() {
+        poll.options
+            .get_mut(option)
+            .ok_or(VoteError::UnknownVoteOption)?
+            .remove_votes(votes)?;
+    }
+    if receipt.commitment.is_some() {
+        poll.unrevealed_commits = poll.unrevealed_commits.saturating_sub(1);
+    } else {
+        poll.participating_weight = poll.participating_weight.saturating_sub(receipt.weight);
+    }
+
+    Ok(())
+}
+
+/// Appends a ranked ballot to the poll's current ballot chunk, creating the chunk for the
+/// first ballot it holds.
+fn store_ranked_ballot<'a>(
+    program_id: &Pubkey,
+    poll: &mut Poll,
+    poll_info: &AccountInfo<'a>,
+    ballot_chunk_info: &AccountInfo<'a>,
+    voter_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    ballot: RankedBallot,
+) -> ProgramResult {
+    let mut ranked = 0u32;
+    for option in &ballot.ranking {
+        if *option as usize >= poll.options.len() {
+            return Err(VoteError::UnknownVoteOption.into());
+        }
+        if ranked & (1 << option) != 0 {
+            return Err(VoteError::InvalidRanking.into());
+        }
+        ranked |= 1 << option;
+    }
+    if ballot.ranking.is_empty() {
+        return Err(VoteError::InvalidRanking.into());
+    }
+
+    check_writable(ballot_chunk_info)?;
+    let index = poll.ballot_count / BALLOTS_PER_CHUNK;
+    let (ballot_chunk_address, bump) = find_ballot_chunk_address(program_id, poll_info.key, index);
+    check_address(ballot_chunk_info, &ballot_chunk_address)?;
+
+    let mut ballot_chunk = if ballot_chunk_info.owner == program_id {
+        load_ballot_chunk(ballot_chunk_info)?
+    } else {
+        create_pda_account(
+            voter_info,
+            ballot_chunk_info,
+            system_program_info,
+            program_id,
+            BallotChunk::space(poll.options.len()),
+            &[
+                BALLOT_CHUNK_SEED,
+                poll_info.key.as_ref(),
+                &index.to_le_bytes(),
+                &[bump],
+            ],
+        )?;
+        BallotChunk {
+            account_type: AccountType::BallotChunk,
+            poll: *poll_info.key,
+            index,
+            ballots: Vec::new(),
+        }
+    };
+    ballot_chunk.ballots.push(ballot);
+    ballot_chunk.serialize(&mut &mut ballot_chunk_info.data.borrow_mut()[..])?;
+
+    poll.ballot_count = poll
+        .ballot_count
+        .checked_add(1)
+        .ok_or(VoteError::CounterOverflow)?;
+
+    Ok(())
+}
+
+/// Rejects polls whose votes can't be moved between options.
+fn check_single_choice(poll: &Poll) -> ProgramResult {
+    if poll.ballot_type != BallotType::SingleChoice || poll.reveal_end_ts.is_some() {
+        return Err(VoteError::UnsupportedBallotType.into());
+    }
+
+    Ok(())
+}
+
+/// Rejects polls that aren't in `state`.
+fn check_state(poll: &Poll, state: ProposalState) -> ProgramResult {
+    if poll.state != state {
+        msg!("Poll is {:?}", poll.state);
+        return Err(VoteError::InvalidProposalState.into());
+    }
+
+    Ok(())
+}
+
+/// Rejects closed polls and polls outside of their voting window.
+fn check_voting_open(poll: &Poll) -> ProgramResult {
+    if poll.is_closed {
+        return Err(VoteError::PollClosed.into());
+    }
+    check_state(poll, ProposalState::Voting)?;
+
+    let clock = Clock::get()?;
+    if clock.unix_timestamp < poll.start_ts {
+        return Err(VoteError::NotStarted.into());
+    }
+    if clock.unix_timestamp >= poll.end_ts {
+        return Err(VoteError::Ended.into());
+    }
+
+    Ok(())
+}
+
+fn load_realm(program_id: &Pubkey, account: &AccountInfo) -> Result<Realm, ProgramError> {
+    check_owner(account, program_id)?;
+
+    let data = account.data.borrow();
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::Realm as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        _ => Err(VoteError::NotInitialized.into()),
+    }
+}
+
+fn load_delegation(program_id: &Pubkey, account: &AccountInfo) -> Result<Delegation, ProgramError> {
+    check_owner(account, program_id)?;
+
+    let data = account.data.borrow();
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::Delegation as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        _ => Err(VoteError::NotInitialized.into()),
+    }
+}
+
+fn load_receipt(program_id: &Pubkey, account: &AccountInfo) -> Result<VoteReceipt, ProgramError> {
+    check_owner(account, program_id)?;
+
+    let data = account.data.borrow();
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::VoteReceipt as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        _ => Err(VoteError::NotVoted.into()),
+    }
+}
+
+fn load_poll(account: &AccountInfo) -> Result<Poll, ProgramError> {
+    let data = account.data.borrow();
+
+    // The account type leads the layout, so zeroed or foreign accounts are rejected
+    // before decoding the rest
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::Poll as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        Some(account_type) if *account_type == AccountType::Closed as u8 => {
+            Err(VoteError::AccountClosed.into())
+        }
+        _ => Err(VoteError::NotInitialized.into()),
+    }
+}
+
+/// Voter escrow owned by the program, the caller checks it belongs to the expected voter.
+fn load_escrow(program_id: &Pubkey, account: &AccountInfo) -> Result<VoterEscrow, ProgramError> {
+    check_owner(account, program_id)?;
+
+    let data = account.data.borrow();
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::VoterEscrow as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        _ => Err(VoteError::NotInitialized.into()),
+    }
+}
+
+fn load_proposal_transaction(account: &AccountInfo) -> Result<ProposalTransaction, ProgramError> {
+    let data = account.data.borrow();
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::ProposalTransaction as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        _ => Err(VoteError::NotInitialized.into()),
+    }
+}
+
+fn load_ballot_chunk(account: &AccountInfo) -> Result<BallotChunk, ProgramError> {
+    let data = account.data.borrow();
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::BallotChunk as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        _ => Err(VoteError::NotInitialized.into()),
+    }
+}
+
+fn load_ranked_tally(account: &AccountInfo) -> Result<RankedTally, ProgramError> {
+    let data = account.data.borrow();
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::RankedTally as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        _ => Err(VoteError::NotInitialized.into()),
+    }
+}
+
+fn load_registry(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+) -> Result<VoterRegistry, ProgramError> {
+    check_owner(account, program_id)?;
+
+    let data = account.data.borrow();
+    match data.first() {
+        Some(account_type) if *account_type == AccountType::VoterRegistry as u8 => {
+            Ok(try_from_slice_unchecked(&data)?)
+        }
+        _ => Err(VoteError::NotInitialized.into()),
+    }
+}
+
+fn check_escrow(escrow: &VoterEscrow, mint: &Pubkey, voter: &Pubkey) -> ProgramResult {
+    if escrow.mint != *mint || escrow.voter != *voter {
+        return Err(VoteError::InvalidVoterEscrow.into());
+    }
+
+    Ok(())
+}
+
+/// Unlocks the creator escrow a proposal kept locked, taken from `remaining`.
+fn unlock_creator_escrow<'a, 'info>(
+    program_id: &Pubkey,
+    poll: &Poll,
+    remaining: &mut impl Iterator<Item = &'a AccountInfo<'info>>,
+) -> ProgramResult
+where
+    'info: 'a,
+{
+    if let Some(creator_escrow) = &poll.creator_escrow {
+        let escrow_info = next_account_info(remaining)?;
+        check_address(escrow_info, creator_escrow)?;
+        check_writable(escrow_info)?;
+        let mut escrow = load_escrow(program_id, escrow_info)?;
+        escrow.active_proposals = escrow.active_proposals.saturating_sub(1);
+        escrow.serialize(&mut &mut escrow_info.data.borrow_mut()[..])?;
+    }
+
+    Ok(())
+}
+
+/// Drops a vote from the voter's escrow, the tokens are unlocked once no vote is left.
+fn release_escrow(
+    program_id: &Pubkey,
+    escrow_info: &AccountInfo,
+    mint: &Pubkey,
+    voter: &Pubkey,
+) -> ProgramResult {
+    check_writable(escrow_info)?;
+    let mut escrow = load_escrow(program_id, escrow_info)?;
+    check_escrow(&escrow, mint, voter)?;
+    escrow.active_votes = escrow.active_votes.saturating_sub(1);
+    escrow.serialize(&mut &mut escrow_info.data.borrow_mut()[..])?;
+
+    Ok(())
+}
+
+/// Total vote weight a percentage quorum is measured against: the governance mint supply,
+/// or the number of registry members in one per wallet polls.
+fn total_weight(
+    program_id: &Pubkey,
+    poll: &Poll,
+    electorate_info: &AccountInfo,
+) -> Result<u64, ProgramError> {
+    match &poll.vote_weight {
+        VoteWeight::Token { mint } | VoteWeight::Escrow { mint } => {
+            check_address(electorate_info, mint)?;
+            check_owner(electorate_info, &spl_token::id())?;
+            let mint = spl_token::state::Mint::unpack(&electorate_info.data.borrow())?;
+            Ok(mint.supply)
+        }
+        _ => {
+            let voter_registry = poll.voter_registry.ok_or(VoteError::InvalidQuorum)?;
+            check_address(electorate_info, &voter_registry)?;
+            let registry = load_registry(program_id, electorate_info)?;
+            Ok(u64::from(registry.member_count))
+        }
+    }
+}
+
+/// Weight of the voter in a poll that doesn't use a Merkle allowlist, taking the voter's
+/// token account or escrow from `remaining`. Escrowed tokens get locked by the vote.
+fn voter_weight<'a, 'info>(
+    program_id: &Pubkey,
+    poll: &Poll,
+    voter: &Pubkey,
+    remaining: &mut impl Iterator<Item = &'a AccountInfo<'info>>,
+) -> Result<u64, ProgramError>
+where
+    'info: 'a,
+{
+    match &poll.vote_weight {
+        VoteWeight::OnePerWallet => Ok(1),
+        VoteWeight::Token { mint } => {
+            let token_account_info = next_account_info(remaining)?;
+            token_balance(token_account_info, mint, voter)
+        }
+        VoteWeight::Escrow { mint } => {
+            let escrow_info = next_account_info(remaining)?;
+            check_writable(escrow_info)?;
+            let mut escrow = load_escrow(program_id, escrow_info)?;
+            check_escrow(&escrow, mint, voter)?;
+            escrow.active_votes = escrow
+                .active_votes
+                .checked_add(1)
+                .ok_or(VoteError::CounterOverflow)?;
+            escrow.serialize(&mut &mut escrow_info.data.borrow_mut()[..])?;
+            Ok(escrow.amount)
+        }
+        VoteWeight::MerkleAllowlist { .. } => Err(VoteError::MerkleProofRequired.into()),
+    }
+}
+
+/// Checks the voter's membership, taken from `remaining`, in registry polls.
+fn check_membership<'a, 'info>(
+    program_id: &Pubkey,
+    poll: &Poll,
+    voter: &Pubkey,
+    remaining: &mut impl Iterator<Item = &'a AccountInfo<'info>>,
+) -> ProgramResult
+where
+    'info: 'a,
+{
+    if let Some(voter_registry) = &poll.voter_registry {
+        let membership_info = next_account_info(remaining)?;
+        let (membership_address, _) = find_member_address(program_id, voter_registry, voter);
+        check_address(membership_info, &membership_address)?;
+        if membership_info.owner != program_id {
+            return Err(VoteError::NotRegistryMember.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Balance of a token account of `mint` owned by `owner`.
+fn token_balance(
+    token_account_info: &AccountInfo,
+    mint: &Pubkey,
+    owner: &Pubkey,
+) -> Result<u64, ProgramError> {
+    if token_account_info.owner != &spl_token::id() {
+        msg!("Token account is not owned by the token program");
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let token_account = spl_token::state::Account::unpack(&token_account_info.data.borrow())?;
+    if token_account.mint != *mint {
+        return Err(VoteError::TokenMintMismatch.into());
+    }
+    if token_account.owner != *owner {
+        return Err(VoteError::TokenOwnerMismatch.into());
+    }
+
+    Ok(token_account.amount)
+}
+
+fn check_authority(poll: &Poll, authority_info: &AccountInfo) -> ProgramResult {
+    if poll.authority != *authority_info.key {
+        return Err(VoteError::InvalidAuthority.into());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        instruction::{
+            add_member, cancel_proposal, cast_approval_vote, cast_escrow_vote, cast_quadratic_vote,
+            cast_ranked_vote, cast_vote, cast_vote_with_proof, change_vote, close_ballots,
+            close_poll, close_poll_account, close_proposal_transaction, close_receipt, commit_vote,
+            create_proposal, create_realm, delegate, delegator_accounts, deposit, execute_proposal,
+            finalize, initialize_poll, initialize_registry, insert_transaction, relinquish_vote,
+            remove_member, retract_vote, reveal_vote, revoke_delegation, sign_off, tally, withdraw,
+        },
+        merkle::{leaf_hash, node_hash},
+        state::{find_receipt_address, EXECUTION_GRACE_PERIOD},
+        test_utils::{
+            mint_account, program_account, set_clock, setup, system_program_account, token_account,
+            token_amount, uncreated, wallet,
+        },
+    };
+    use borsh::BorshDeserialize;
+    use solana_program::{rent::Rent, system_program};
+
+    fn new_poll(program_id: &Pubkey, creator: &AccountInfo<'static>) -> AccountInfo<'static> {
+        uncreated(find_poll_address(program_id, creator.key, 0).0)
+    }
+
+    fn init_poll(
+        program_id: &Pubkey,
+        poll: &AccountInfo<'static>,
+        creator: &AccountInfo<'static>,
+        title: &str,
+        options: &[&str],
+    ) -> ProgramResult {
+        init_weighted_poll(
+            program_id,
+            poll,
+            creator,
+            title,
+            options,
+            VoteWeight::OnePerWallet,
+        )
+    }
+
+    fn init_weighted_poll(
+        program_id: &Pubkey,
+        poll: &AccountInfo<'static>,
+        creator: &AccountInfo<'static>,
+        title: &str,
+        options: &[&str],
+        vote_weight: VoteWeight,
+    ) -> ProgramResult {
+        let instruction = initialize_poll(
+            program_id,
+            creator.key,
+            0,
+            title.into(),
+            "https://vote.hanmaster.ru/poll".into(),
+            [7; 32],
+            options.iter().map(|label| label.to_string()).collect(),
+            10,
+            100,
+            vote_weight,
+            None,
+            Quorum::None,
+            Threshold::SimpleMajority,
+            BallotType::SingleChoice,
+            None,
+        );
+        Processor::process(
+            program_id,
+            &[poll.clone(), creator.clone(), system_program_account()],
+            &instruction.data,
+        )
+    }
+
+    fn vote(
+        program_id: &Pubkey,
+        poll: &AccountInfo<'static>,
+        voter: &AccountInfo<'static>,
+        receipt: &AccountInfo<'static>,
+        option: u8,
+    ) -> ProgramResult {
+        let instruction = cast_vote(program_id, poll.key, voter.key, None, None, option);
+        Processor::process(
+            program_id,
+            &[
+                poll.clone(),
+                voter.clone(),
+                receipt.clone(),
+                system_program_account(),
+            ],
+            &instruction.data,
+        )
+    }
+
+    fn vote_with_tokens(
+        program_id: &Pubkey,
+        poll: &AccountInfo<'static>,
+        voter: &AccountInfo<'static>,
+        receipt: &AccountInfo<'static>,
+        token_account: &AccountInfo<'static>,
+        option: u8,
+    ) -> ProgramResult {
+        let instruction = cast_vote(
+            program_id,
+            poll.key,
+            voter.key,
+            Some(token_account.key),
+            None,
+            option,
+        );
+        Processor::process(
+            program_id,
+            &[
+                poll.clone(),
+                voter.clone(),
+                receipt.clone(),
+                system_program_account(),
+                token_account.clone(),
+            ],
+            &instruction.data,
+        )
+    }
+
+    fn receipt_for(
+        program_id: &Pubkey,
+        poll: &AccountInfo<'static>,
+        voter: &AccountInfo<'static>,
+    ) -> AccountInfo<'static> {
+        uncreated(find_receipt_address(program_id, poll.key, voter.key).0)
+    }
+
+    #[test]
+    fn test_initialize_poll() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+
+        assert_eq!(
+            init_poll(&program_id, &poll, &creator, "Lunch", &["pizza"]),
+            Err(VoteError::InvalidOptionCount.into())
+        );
+        assert_eq!(
+            init_poll(
+                &program_id,
+                &poll,
+                &creator,
+                &"a".repeat(65),
+                &["pizza", "sushi"]
+            ),
+            Err(VoteError::TitleTooLong.into())
+        );
+
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+        assert_eq!(poll.owner, &program_id);
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.account_type, AccountType::Poll);
+        assert_eq!(state.authority, *creator.key);
+        assert_eq!(state.title, "Lunch");
+        assert_eq!(state.content_hash, [7; 32]);
+        assert_eq!(state.options[1].label, "sushi");
+
+        assert_eq!(
+            init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+    }
+
+    #[test]
+    fn test_cast_vote() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        set_clock(10);
+
+        assert_eq!(
+            vote(&program_id, &poll, &voter, &receipt, 2),
+            Err(VoteError::UnknownVoteOption.into())
+        );
+
+        vote(&program_id, &poll, &voter, &receipt, 1).unwrap();
+        let state = VoteReceipt::try_from_slice(&receipt.data.borrow()).unwrap();
+        assert_eq!(state.voter, *voter.key);
+        assert_eq!(state.option, 1);
+
+        assert_eq!(
+            vote(&program_id, &poll, &voter, &receipt, 0),
+            Err(VoteError::AlreadyVoted.into())
+        );
+
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[0].votes, 0);
+        assert_eq!(state.options[1].votes, 1);
+    }
+
+    #[test]
+    fn test_close_poll() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+
+        let stranger = wallet();
+        let instruction = close_poll(&program_id, poll.key, stranger.key);
+        assert_eq!(
+            Processor::process(&program_id, &[poll.clone(), stranger], &instruction.data),
+            Err(VoteError::InvalidAuthority.into())
+        );
+
+        set_clock(0);
+        let instruction = close_poll(&program_id, poll.key, creator.key);
+        let accounts = [poll.clone(), creator];
+        Processor::process(&program_id, &accounts, &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.state, ProposalState::Cancelled);
+        assert_eq!(state.outcome, Outcome::Cancelled);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::InvalidProposalState.into())
+        );
+
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        set_clock(10);
+        assert_eq!(
+            vote(&program_id, &poll, &voter, &receipt, 0),
+            Err(VoteError::PollClosed.into())
+        );
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+        assert_eq!(
+            Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data),
+            Err(VoteError::AlreadyFinalized.into())
+        );
+
+        let uninitialized = program_account(Pubkey::new_unique(), program_id, vec![0; 64]);
+        let instruction = close_poll(&program_id, uninitialized.key, voter.key);
+        assert_eq!(
+            Processor::process(&program_id, &[uninitialized, voter], &instruction.data),
+            Err(VoteError::NotInitialized.into())
+        );
+
+        // An ended poll can't be closed to throw away its result
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+        set_clock(100);
+        let instruction = close_poll(&program_id, poll.key, creator.key);
+        assert_eq!(
+            Processor::process(&program_id, &[poll.clone(), creator], &instruction.data),
+            Err(VoteError::Ended.into())
+        );
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.state, ProposalState::Voting);
+    }
+
+    #[test]
+    fn test_voting_window() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+
+        set_clock(9);
+        assert_eq!(
+            vote(&program_id, &poll, &voter, &receipt, 0),
+            Err(VoteError::NotStarted.into())
+        );
+
+        set_clock(99);
+        vote(&program_id, &poll, &voter, &receipt, 0).unwrap();
+        assert_eq!(
+            Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data),
+            Err(VoteError::VotingNotEnded.into())
+        );
+
+        set_clock(100);
+        let late_voter = wallet();
+        let late_receipt = receipt_for(&program_id, &poll, &late_voter);
+        assert_eq!(
+            vote(&program_id, &poll, &late_voter, &late_receipt, 0),
+            Err(VoteError::Ended.into())
+        );
+
+        Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data).unwrap();
+        assert!(
+            Poll::try_from_slice(&poll.data.borrow())
+                .unwrap()
+                .is_finalized
+        );
+        assert_eq!(
+            Processor::process(&program_id, &[poll], &instruction.data),
+            Err(VoteError::AlreadyFinalized.into())
+        );
+    }
+
+    #[test]
+    fn test_counter_overflow() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+        set_clock(10);
+
+        let mut state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        state.options[0].votes = u64::MAX;
+        state
+            .serialize(&mut &mut poll.data.borrow_mut()[..])
+            .unwrap();
+
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        assert_eq!(
+            vote(&program_id, &poll, &voter, &receipt, 0),
+            Err(VoteError::CounterOverflow.into())
+        );
+        vote(&program_id, &poll, &voter, &receipt, 1).unwrap();
+    }
+
+    #[test]
+    fn test_token_weighted_vote() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_weighted_poll(
+            &program_id,
+            &poll,
+            &creator,
+            "Treasury",
+            &["fund", "reject"],
+            VoteWeight::Token { mint },
+        )
+        .unwrap();
+        set_clock(10);
+
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let foreign_mint = token_account(&Pubkey::new_unique(), voter.key, 500);
+        assert_eq!(
+            vote_with_tokens(&program_id, &poll, &voter, &receipt, &foreign_mint, 0),
+            Err(VoteError::TokenMintMismatch.into())
+        );
+        let foreign_owner = token_account(&mint, &Pubkey::new_unique(), 500);
+        assert_eq!(
+            vote_with_tokens(&program_id, &poll, &voter, &receipt, &foreign_owner, 0),
+            Err(VoteError::TokenOwnerMismatch.into())
+        );
+        let empty = token_account(&mint, voter.key, 0);
+        assert_eq!(
+            vote_with_tokens(&program_id, &poll, &voter, &receipt, &empty, 0),
+            Err(VoteError::NoVoteWeight.into())
+        );
+
+        let tokens = token_account(&mint, voter.key, 500);
+        vote_with_tokens(&program_id, &poll, &voter, &receipt, &tokens, 0).unwrap();
+
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[0].votes, 500);
+        let receipt = VoteReceipt::try_from_slice(&receipt.data.borrow()).unwrap();
+        assert_eq!(receipt.weight, 500);
+    }
+
+    #[test]
+    fn test_escrow_vote() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let mint = mint_account(1_000);
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_weighted_poll(
+            &program_id,
+            &poll,
+            &creator,
+            "Treasury",
+            &["fund", "reject"],
+            VoteWeight::Escrow { mint: *mint.key },
+        )
+        .unwrap();
+
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let tokens = token_account(mint.key, voter.key, 1000);
+        let escrow = uncreated(find_escrow_address(&program_id, mint.key, voter.key).0);
+        let vault = uncreated(find_escrow_vault_address(&program_id, mint.key, voter.key).0);
+        let token_program = program_account(spl_token::id(), Pubkey::default(), vec![]);
+
+        let instruction = deposit(&program_id, mint.key, voter.key, tokens.key, 600);
+        let deposit_accounts = [
+            escrow.clone(),
+            vault.clone(),
+            voter.clone(),
+            tokens.clone(),
+            mint.clone(),
+            system_program_account(),
+            token_program.clone(),
+        ];
+        Processor::process(&program_id, &deposit_accounts, &instruction.data).unwrap();
+        assert_eq!(token_amount(&vault), 600);
+        assert_eq!(token_amount(&tokens), 400);
+
+        set_clock(10);
+        let vote_accounts = [
+            poll.clone(),
+            voter.clone(),
+            receipt.clone(),
+            system_program_account(),
+            escrow.clone(),
+        ];
+        let instruction = cast_escrow_vote(&program_id, poll.key, voter.key, mint.key, None, 0);
+        Processor::process(&program_id, &vote_accounts, &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[0].votes, 600);
+
+        let withdraw_accounts = [
+            escrow.clone(),
+            vault.clone(),
+            voter.clone(),
+            tokens.clone(),
+            token_program,
+        ];
+        let instruction = withdraw(&program_id, mint.key, voter.key, tokens.key, 600);
+        assert_eq!(
+            Processor::process(&program_id, &withdraw_accounts, &instruction.data),
+            Err(VoteError::EscrowLocked.into())
+        );
+
+        // Relinquishing while voting is open takes the vote back out of the tally
+        let relinquish_accounts = [poll.clone(), voter.clone(), receipt.clone(), escrow.clone()];
+        let relinquish = relinquish_vote(&program_id, poll.key, voter.key, mint.key);
+        Processor::process(&program_id, &relinquish_accounts, &relinquish.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[0].votes, 0);
+        assert_eq!(receipt.lamports(), 0);
+
+        let instruction = cast_escrow_vote(&program_id, poll.key, voter.key, mint.key, None, 1);
+        Processor::process(&program_id, &vote_accounts, &instruction.data).unwrap();
+
+        // Afterwards the tally stands and only the tokens are released
+        set_clock(100);
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+        Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data).unwrap();
+        let close = close_poll_account(&program_id, poll.key, creator.key, creator.key);
+        let close_accounts = [poll.clone(), creator.clone(), creator.clone()];
+        assert_eq!(
+            Processor::process(&program_id, &close_accounts, &close.data),
+            Err(VoteError::ReceiptsOutstanding.into())
+        );
+        let instruction = close_receipt(&program_id, poll.key, voter.key, Some(mint.key));
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[poll.clone(), receipt.clone(), voter.clone()],
+                &instruction.data
+            ),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        // Anyone can close the receipt, which releases the tokens without the voter
+        let lamports = voter.lamports();
+        let receipt_lamports = receipt.lamports();
+        let close_receipt_accounts = [poll.clone(), receipt.clone(), voter.clone(), escrow.clone()];
+        Processor::process(&program_id, &close_receipt_accounts, &instruction.data).unwrap();
+        assert_eq!(voter.lamports(), lamports + receipt_lamports);
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[1].votes, 600);
+        let state = VoterEscrow::try_from_slice(&escrow.data.borrow()).unwrap();
+        assert_eq!(state.active_votes, 0);
+        Processor::process(&program_id, &close_accounts, &close.data).unwrap();
+
+        let instruction = withdraw(&program_id, mint.key, voter.key, tokens.key, 601);
+        assert_eq!(
+            Processor::process(&program_id, &withdraw_accounts, &instruction.data),
+            Err(VoteError::InsufficientEscrow.into())
+        );
+        let instruction = withdraw(&program_id, mint.key, voter.key, tokens.key, 600);
+        Processor::process(&program_id, &withdraw_accounts, &instruction.data).unwrap();
+        assert_eq!(token_amount(&tokens), 1000);
+        assert_eq!(token_amount(&vault), 0);
+    }
+
+    #[test]
+    fn test_change_and_retract_vote() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let accounts = [poll.clone(), voter.clone(), receipt.clone()];
+        set_clock(10);
+
+        let instruction = change_vote(&program_id, poll.key, voter.key, 1);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::NotVoted.into())
+        );
+
+        vote(&program_id, &poll, &voter, &receipt, 0).unwrap();
+        Processor::process(&program_id, &accounts, &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[0].votes, 0);
+        assert_eq!(state.options[1].votes, 1);
+        assert_eq!(
+            VoteReceipt::try_from_slice(&receipt.data.borrow())
+                .unwrap()
+                .option,
+            1
+        );
+
+        let instruction = change_vote(&program_id, poll.key, voter.key, 2);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::UnknownVoteOption.into())
+        );
+
+        let lamports = voter.lamports();
+        let receipt_lamports = receipt.lamports();
+        let instruction = retract_vote(&program_id, poll.key, voter.key, None, &[]);
+        Processor::process(&program_id, &accounts, &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[1].votes, 0);
+        assert_eq!(receipt.lamports(), 0);
+        assert_eq!(voter.lamports(), lamports + receipt_lamports);
+
+        // A retracted vote can be cast again, but nothing changes once voting has ended
+        vote(&program_id, &poll, &voter, &receipt, 0).unwrap();
+        set_clock(100);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::Ended.into())
+        );
+        let instruction = change_vote(&program_id, poll.key, voter.key, 1);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::Ended.into())
+        );
+    }
+
+    #[test]
+    fn test_account_validation() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let instruction = cast_vote(&program_id, poll.key, voter.key, None, None, 0);
+        set_clock(10);
+
+        let mut unsigned_voter = voter.clone();
+        unsigned_voter.is_signer = false;
+        let mut readonly_poll = poll.clone();
+        readonly_poll.is_writable = false;
+        let foreign_poll = program_account(*poll.key, Pubkey::new_unique(), vec![0; 64]);
+        let other_receipt = uncreated(Pubkey::new_unique());
+        let not_system_program = uncreated(Pubkey::new_unique());
+
+        let cases = [
+            (
+                [
+                    poll.clone(),
+                    unsigned_voter,
+                    receipt.clone(),
+                    system_program_account(),
+                ],
+                ProgramError::MissingRequiredSignature,
+            ),
+            (
+                [
+                    readonly_poll,
+                    voter.clone(),
+                    receipt.clone(),
+                    system_program_account(),
+                ],
+                VoteError::AccountNotWritable.into(),
+            ),
+            (
+                [
+                    foreign_poll,
+                    voter.clone(),
+                    receipt.clone(),
+                    system_program_account(),
+                ],
+                ProgramError::IncorrectProgramId,
+            ),
+            (
+                [
+                    poll.clone(),
+                    voter.clone(),
+                    other_receipt,
+                    system_program_account(),
+                ],
+                ProgramError::InvalidSeeds,
+            ),
+            (
+                [
+                    poll.clone(),
+                    voter.clone(),
+                    receipt.clone(),
+                    not_system_program,
+                ],
+                ProgramError::IncorrectProgramId,
+            ),
+        ];
+        for (accounts, error) in cases {
+            assert_eq!(
+                Processor::process(&program_id, &accounts, &instruction.data),
+                Err(error)
+            );
+        }
+
+        vote(&program_id, &poll, &voter, &receipt, 0).unwrap();
+    }
+
+    #[test]
+    fn test_voter_registry() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let (registry_key, _) = find_registry_address(&program_id, creator.key, 0);
+        let registry = uncreated(registry_key);
+        let instruction = initialize_registry(&program_id, creator.key, 0);
+        Processor::process(
+            &program_id,
+            &[registry.clone(), creator.clone(), system_program_account()],
+            &instruction.data,
+        )
+        .unwrap();
+
+        let member = wallet();
+        let membership = uncreated(find_member_address(&program_id, registry.key, member.key).0);
+        let add = add_member(&program_id, registry.key, creator.key, member.key);
+        let stranger = wallet();
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[
+                    registry.clone(),
+                    stranger.clone(),
+                    membership.clone(),
+                    system_program_account()
+                ],
+                &add.data,
+            ),
+            Err(VoteError::InvalidAuthority.into())
+        );
+        let add_accounts = [
+            registry.clone(),
+            creator.clone(),
+            membership.clone(),
+            system_program_account(),
+        ];
+        Processor::process(&program_id, &add_accounts, &add.data).unwrap();
+        assert_eq!(
+            Processor::process(&program_id, &add_accounts, &add.data),
+            Err(VoteError::AlreadyRegistryMember.into())
+        );
+        let state = VoterRegistry::try_from_slice(&registry.data.borrow()).unwrap();
+        assert_eq!(state.member_count, 1);
+
+        let poll = new_poll(&program_id, &creator);
+        let instruction = initialize_poll(
+            &program_id,
+            creator.key,
+            0,
+            "Team lunch".into(),
+            "https://vote.hanmaster.ru/poll".into(),
+            [7; 32],
+            vec!["pizza".into(), "sushi".into()],
+            10,
+            100,
+            VoteWeight::OnePerWallet,
+            Some(*registry.key),
+            Quorum::None,
+            Threshold::SimpleMajority,
+            BallotType::SingleChoice,
+            None,
+        );
+        Processor::process(
+            &program_id,
+            &[
+                poll.clone(),
+                creator.clone(),
+                system_program_account(),
+                registry.clone(),
+            ],
+            &instruction.data,
+        )
+        .unwrap();
+        set_clock(10);
+
+        let member_vote = |voter: &AccountInfo<'static>, membership: &AccountInfo<'static>| {
+            let instruction = cast_vote(
+                &program_id,
+                poll.key,
+                voter.key,
+                None,
+                Some(registry.key),
+                0,
+            );
+            Processor::process(
+                &program_id,
+                &[
+                    poll.clone(),
+                    voter.clone(),
+                    receipt_for(&program_id, &poll, voter),
+                    system_program_account(),
+                    membership.clone(),
+                ],
+                &instruction.data,
+            )
+        };
+        let outsider_membership =
+            uncreated(find_member_address(&program_id, registry.key, stranger.key).0);
+        assert_eq!(
+            member_vote(&stranger, &outsider_membership),
+            Err(VoteError::NotRegistryMember.into())
+        );
+        assert_eq!(
+            member_vote(&stranger, &membership),
+            Err(ProgramError::InvalidSeeds)
+        );
+
+        let remove = remove_member(&program_id, registry.key, creator.key, member.key);
+        let remove_accounts = [registry.clone(), creator.clone(), membership.clone()];
+        let other_member = wallet();
+        let other_membership =
+            uncreated(find_member_address(&program_id, registry.key, other_member.key).0);
+        let add = add_member(&program_id, registry.key, creator.key, other_member.key);
+        Processor::process(
+            &program_id,
+            &[
+                registry.clone(),
+                creator.clone(),
+                other_membership.clone(),
+                system_program_account(),
+            ],
+            &add.data,
+        )
+        .unwrap();
+        member_vote(&other_member, &other_membership).unwrap();
+
+        Processor::process(&program_id, &remove_accounts, &remove.data).unwrap();
+        assert_eq!(
+            member_vote(&member, &membership),
+            Err(VoteError::NotRegistryMember.into())
+        );
+        assert_eq!(
+            Processor::process(&program_id, &remove_accounts, &remove.data),
+            Err(VoteError::NotRegistryMember.into())
+        );
+        let state = VoterRegistry::try_from_slice(&registry.data.borrow()).unwrap();
+        assert_eq!(state.member_count, 1);
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[0].votes, 1);
+    }
+
+    #[test]
+    fn test_merkle_allowlist() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let (alice, bob, carol) = (wallet(), wallet(), wallet());
+        let alice_leaf = leaf_hash(alice.key, 5);
+        let bob_leaf = leaf_hash(bob.key, 7);
+        let carol_leaf = leaf_hash(carol.key, 1);
+        let alice_bob = node_hash(&alice_leaf, &bob_leaf);
+        let root = node_hash(&alice_bob, &carol_leaf);
+
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_weighted_poll(
+            &program_id,
+            &poll,
+            &creator,
+            "Council",
+            &["yes", "no"],
+            VoteWeight::MerkleAllowlist { root },
+        )
+        .unwrap();
+        set_clock(10);
+
+        let vote_with_proof = |voter: &AccountInfo<'static>, weight: u64, proof: Vec<[u8; 32]>| {
+            let instruction =
+                cast_vote_with_proof(&program_id, poll.key, voter.key, None, 0, weight, proof);
+            Processor::process(
+                &program_id,
+                &[
+                    poll.clone(),
+                    voter.clone(),
+                    receipt_for(&program_id, &poll, voter),
+                    system_program_account(),
+                ],
+                &instruction.data,
+            )
+        };
+
+        let receipt = receipt_for(&program_id, &poll, &alice);
+        assert_eq!(
+            vote(&program_id, &poll, &alice, &receipt, 0),
+            Err(VoteError::MerkleProofRequired.into())
+        );
+        assert_eq!(
+            vote_with_proof(&alice, 50, vec![bob_leaf, carol_leaf]),
+            Err(VoteError::InvalidMerkleProof.into())
+        );
+        assert_eq!(
+            vote_with_proof(&bob, 5, vec![bob_leaf, carol_leaf]),
+            Err(VoteError::InvalidMerkleProof.into())
+        );
+        assert_eq!(
+            vote_with_proof(&wallet(), 7, vec![alice_bob]),
+            Err(VoteError::InvalidMerkleProof.into())
+        );
+
+        vote_with_proof(&alice, 5, vec![bob_leaf, carol_leaf]).unwrap();
+        vote_with_proof(&carol, 1, vec![alice_bob]).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[0].votes, 6);
+    }
+
+    #[test]
+    fn test_close_poll_account() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Lunch", &["pizza", "sushi"]).unwrap();
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        set_clock(10);
+        vote(&program_id, &poll, &voter, &receipt, 0).unwrap();
+
+        let recipient = wallet();
+        let instruction = close_poll_account(&program_id, poll.key, creator.key, recipient.key);
+        let accounts = [poll.clone(), creator.clone(), recipient.clone()];
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::NotFinalized.into())
+        );
+        let stranger = wallet();
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[poll.clone(), stranger, recipient.clone()],
+                &instruction.data
+            ),
+            Err(VoteError::InvalidAuthority.into())
+        );
+
+        let close = close_receipt(&program_id, poll.key, voter.key, None);
+        let receipt_accounts = [poll.clone(), receipt.clone(), voter.clone()];
+        assert_eq!(
+            Processor::process(&program_id, &receipt_accounts, &close.data),
+            Err(VoteError::NotFinalized.into())
+        );
+
+        set_clock(100);
+        let finalize = finalize(&program_id, poll.key, None, None, None);
+        Processor::process(&program_id, std::slice::from_ref(&poll), &finalize.data).unwrap();
+
+        // Receipts go first, so none outlive the poll and count in a poll re-created later
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::ReceiptsOutstanding.into())
+        );
+        let impostor = wallet();
+        let forged_receipt = program_account(
+            find_receipt_address(&program_id, poll.key, impostor.key).0,
+            program_id,
+            vec![AccountType::Poll as u8; 128],
+        );
+        let forged_close = close_receipt(&program_id, poll.key, impostor.key, None);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[poll.clone(), forged_receipt, impostor],
+                &forged_close.data
+            ),
+            Err(VoteError::NotVoted.into())
+        );
+        let (receipt_lamports, voter_lamports) = (receipt.lamports(), voter.lamports());
+        Processor::process(&program_id, &receipt_accounts, &close.data).unwrap();
+        assert_eq!(receipt.lamports(), 0);
+        assert_eq!(voter.lamports(), voter_lamports + receipt_lamports);
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.open_receipts, 0);
+
+        let (poll_lamports, recipient_lamports) = (poll.lamports(), recipient.lamports());
+        Processor::process(&program_id, &accounts, &instruction.data).unwrap();
+        assert_eq!(poll.lamports(), 0);
+        assert_eq!(recipient.lamports(), recipient_lamports + poll_lamports);
+        assert_eq!(poll.data.borrow()[0], AccountType::Closed as u8);
+        assert!(poll.data.borrow()[1..].iter().all(|byte| *byte == 0));
+
+        // Lamports sent to the tombstone don't bring the poll back
+        **poll.lamports.borrow_mut() = poll_lamports;
+        assert_eq!(
+            Processor::process(&program_id, std::slice::from_ref(&poll), &finalize.data),
+            Err(VoteError::AccountClosed.into())
+        );
+    }
+
+    #[test]
+    fn test_poll_outcome() {
+        setup();
+        let program_id = Pubkey::new_unique();
+
+        let decide = |quorum: Quorum, threshold: Threshold, ballots: &[u8]| {
+            let creator = wallet();
+            let poll = new_poll(&program_id, &creator);
+            let instruction = initialize_poll(
+                &program_id,
+                creator.key,
+                0,
+                "Motion".into(),
+                "https://vote.hanmaster.ru/poll".into(),
+                [7; 32],
+                vec!["yes".into(), "no".into(), "abstain".into()],
+                10,
+                100,
+                VoteWeight::OnePerWallet,
+                None,
+                quorum,
+                threshold,
+                BallotType::SingleChoice,
+                None,
+            );
+            Processor::process(
+                &program_id,
+                &[poll.clone(), creator.clone(), system_program_account()],
+                &instruction.data,
+            )
+            .unwrap();
+
+            set_clock(10);
+            for option in ballots {
+                let voter = wallet();
+                let receipt = receipt_for(&program_id, &poll, &voter);
+                vote(&program_id, &poll, &voter, &receipt, *option).unwrap();
+            }
+
+            set_clock(100);
+            let instruction = finalize(&program_id, poll.key, None, None, None);
+            Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data)
+                .unwrap();
+            let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+            // Without proposal transactions there is nothing left to execute
+            assert_eq!(
+                state.state == ProposalState::Executed,
+                state.outcome == Outcome::Passed
+            );
+            state.outcome
+        };
+
+        let quorum = Quorum::Absolute { votes: 3 };
+        assert_eq!(
+            decide(quorum.clone(), Threshold::SimpleMajority, &[0, 0]),
+            Outcome::QuorumNotMet
+        );
+        assert_eq!(
+            decide(quorum.clone(), Threshold::SimpleMajority, &[0, 0, 1, 2]),
+            Outcome::Rejected
+        );
+        assert_eq!(
+            decide(quorum.clone(), Threshold::MajorityOfYesNo, &[0, 0, 1, 2]),
+            Outcome::Passed
+        );
+        assert_eq!(
+            decide(quorum.clone(), Threshold::TwoThirds, &[0, 0, 1]),
+            Outcome::Passed
+        );
+        assert_eq!(
+            decide(quorum, Threshold::TwoThirds, &[0, 0, 1, 2]),
+            Outcome::Rejected
+        );
+        assert_eq!(
+            decide(Quorum::None, Threshold::SimpleMajority, &[]),
+            Outcome::Rejected
+        );
+
+        // Percentage quorums are measured against the governance mint supply
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        let mint = mint_account(1_000);
+        let init = |vote_weight: VoteWeight, percent: u8| {
+            initialize_poll(
+                &program_id,
+                creator.key,
+                0,
+                "Treasury".into(),
+                "https://vote.hanmaster.ru/poll".into(),
+                [7; 32],
+                vec!["fund".into(), "reject".into()],
+                10,
+                100,
+                vote_weight,
+                None,
+                Quorum::Percent { percent },
+                Threshold::SimpleMajority,
+                BallotType::SingleChoice,
+                None,
+            )
+        };
+        let init_accounts = [poll.clone(), creator.clone(), system_program_account()];
+        for (vote_weight, percent) in [
+            (VoteWeight::Token { mint: *mint.key }, 0),
+            (VoteWeight::Token { mint: *mint.key }, 101),
+            (VoteWeight::OnePerWallet, 50),
+            (VoteWeight::MerkleAllowlist { root: [1; 32] }, 50),
+        ] {
+            assert_eq!(
+                Processor::process(
+                    &program_id,
+                    &init_accounts,
+                    &init(vote_weight, percent).data
+                ),
+                Err(VoteError::InvalidQuorum.into())
+            );
+        }
+        let instruction = init(VoteWeight::Token { mint: *mint.key }, 50);
+        Processor::process(&program_id, &init_accounts, &instruction.data).unwrap();
+
+        set_clock(10);
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let tokens = token_account(mint.key, voter.key, 400);
+        vote_with_tokens(&program_id, &poll, &voter, &receipt, &tokens, 0).unwrap();
+
+        set_clock(100);
+        let instruction = finalize(&program_id, poll.key, Some(mint.key), None, None);
+        assert_eq!(
+            Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        let other_mint = mint_account(100);
+        assert_eq!(
+            Processor::process(&program_id, &[poll.clone(), other_mint], &instruction.data),
+            Err(ProgramError::InvalidSeeds)
+        );
+        Processor::process(&program_id, &[poll.clone(), mint], &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.outcome, Outcome::QuorumNotMet);
+        assert!(state.is_finalized);
+    }
+
+    #[test]
+    fn test_execute_proposal() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Grant", &["yes", "no"]).unwrap();
+
+        let (treasury_key, _) = find_treasury_address(&program_id, creator.key);
+        let treasury = program_account(treasury_key, system_program::id(), vec![]);
+        let treasury_lamports = treasury.lamports();
+        let recipient = wallet();
+        let recipient_lamports = recipient.lamports();
+        let instructions: Vec<InstructionData> =
+            vec![system_instruction::transfer(treasury.key, recipient.key, 1_000).into()];
+
+        let proposal_transaction =
+            uncreated(find_proposal_transaction_address(&program_id, poll.key, 0).0);
+        let insert = insert_transaction(
+            &program_id,
+            poll.key,
+            creator.key,
+            0,
+            50,
+            instructions.clone(),
+        );
+        let insert_accounts = [
+            poll.clone(),
+            creator.clone(),
+            proposal_transaction.clone(),
+            system_program_account(),
+        ];
+
+        set_clock(0);
+        let intruder = wallet();
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[
+                    poll.clone(),
+                    intruder,
+                    proposal_transaction.clone(),
+                    system_program_account(),
+                ],
+                &insert.data,
+            ),
+            Err(VoteError::InvalidAuthority.into())
+        );
+        Processor::process(&program_id, &insert_accounts, &insert.data).unwrap();
+        assert_eq!(
+            Processor::process(&program_id, &insert_accounts, &insert.data),
+            Err(ProgramError::AccountAlreadyInitialized)
+        );
+        let state = ProposalTransaction::try_from_slice(
+            &proposal_transaction.data.borrow()[..proposal_transaction.data_len() - 8],
+        )
+        .unwrap();
+        assert_eq!(state.instructions, instructions);
+        assert_eq!(state.executed_at, None);
+
+        set_clock(10);
+        let late = insert_transaction(
+            &program_id,
+            poll.key,
+            creator.key,
+            1,
+            0,
+            instructions.clone(),
+        );
+        let late_transaction =
+            uncreated(find_proposal_transaction_address(&program_id, poll.key, 1).0);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[
+                    poll.clone(),
+                    creator.clone(),
+                    late_transaction,
+                    system_program_account(),
+                ],
+                &late.data,
+            ),
+            Err(VoteError::VotingStarted.into())
+        );
+
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        vote(&program_id, &poll, &voter, &receipt, 0).unwrap();
+
+        let execute = execute_proposal(&program_id, poll.key, creator.key, 0, &instructions);
+        let execute_accounts = [
+            poll.clone(),
+            proposal_transaction.clone(),
+            treasury.clone(),
+            system_program_account(),
+            recipient.clone(),
+        ];
+        set_clock(100);
+        assert_eq!(
+            Processor::process(&program_id, &execute_accounts, &execute.data),
+            Err(VoteError::ProposalNotPassed.into())
+        );
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+        Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data).unwrap();
+        assert_eq!(
+            Processor::process(&program_id, &execute_accounts, &execute.data),
+            Err(VoteError::HoldUpTimeNotElapsed.into())
+        );
+        let close = close_poll_account(&program_id, poll.key, creator.key, creator.key);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[poll.clone(), creator.clone(), creator.clone()],
+                &close.data
+            ),
+            Err(VoteError::InvalidProposalState.into())
+        );
+        let close = close_proposal_transaction(&program_id, poll.key, creator.key, 0);
+        let close_accounts = [poll.clone(), proposal_transaction.clone(), creator.clone()];
+        assert_eq!(
+            Processor::process(&program_id, &close_accounts, &close.data),
+            Err(VoteError::InvalidProposalState.into())
+        );
+
+        set_clock(150);
+        let foreign_treasury = program_account(Pubkey::new_unique(), system_program::id(), vec![]);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[
+                    poll.clone(),
+                    proposal_transaction.clone(),
+                    foreign_treasury,
+                    system_program_account(),
+                    recipient.clone(),
+                ],
+                &execute.data,
+            ),
+            Err(ProgramError::InvalidSeeds)
+        );
+        Processor::process(&program_id, &execute_accounts, &execute.data).unwrap();
+        assert_eq!(treasury.lamports(), treasury_lamports - 1_000);
+        assert_eq!(recipient.lamports(), recipient_lamports + 1_000);
+        let state =
+            ProposalTransaction::try_from_slice(&proposal_transaction.data.borrow()).unwrap();
+        assert_eq!(state.executed_at, Some(150));
+
+        assert_eq!(
+            Processor::process(&program_id, &execute_accounts, &execute.data),
+            Err(VoteError::AlreadyExecuted.into())
+        );
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.state, ProposalState::Executed);
+
+        // Executed transactions are closed to the authority that paid for them
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[poll.clone(), proposal_transaction.clone(), wallet()],
+                &close.data
+            ),
+            Err(VoteError::InvalidAuthority.into())
+        );
+        let (transaction_lamports, creator_lamports) =
+            (proposal_transaction.lamports(), creator.lamports());
+        Processor::process(&program_id, &close_accounts, &close.data).unwrap();
+        assert_eq!(proposal_transaction.lamports(), 0);
+        assert_eq!(creator.lamports(), creator_lamports + transaction_lamports);
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(
+            (state.transaction_count, state.executed_transaction_count),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn test_expired_proposal_transaction() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_poll(&program_id, &poll, &creator, "Grant", &["yes", "no"]).unwrap();
+
+        // The treasury can never afford the transfer, so executing it always fails
+        let (treasury_key, _) = find_treasury_address(&program_id, creator.key);
+        let treasury = program_account(treasury_key, system_program::id(), vec![]);
+        let recipient = wallet();
+        let instructions: Vec<InstructionData> =
+            vec![system_instruction::transfer(treasury.key, recipient.key, u64::MAX).into()];
+        let proposal_transaction =
+            uncreated(find_proposal_transaction_address(&program_id, poll.key, 0).0);
+        let insert = insert_transaction(
+            &program_id,
+            poll.key,
+            creator.key,
+            0,
+            50,
+            instructions.clone(),
+        );
+        set_clock(0);
+        Processor::process(
+            &program_id,
+            &[
+                poll.clone(),
+                creator.clone(),
+                proposal_transaction.clone(),
+                system_program_account(),
+            ],
+            &insert.data,
+        )
+        .unwrap();
+
+        set_clock(10);
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        vote(&program_id, &poll, &voter, &receipt, 0).unwrap();
+        set_clock(100);
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+        Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data).unwrap();
+
+        let execute = execute_proposal(&program_id, poll.key, creator.key, 0, &instructions);
+        let execute_accounts = [
+            poll.clone(),
+            proposal_transaction.clone(),
+            treasury,
+            system_program_account(),
+            recipient,
+        ];
+        let close = close_proposal_transaction(&program_id, poll.key, creator.key, 0);
+        let close_accounts = [poll.clone(), proposal_transaction.clone(), creator.clone()];
+        let expires_at = 150 + EXECUTION_GRACE_PERIOD;
+        set_clock(expires_at - 1);
+        assert_eq!(
+            Processor::process(&program_id, &close_accounts, &close.data),
+            Err(VoteError::InvalidProposalState.into())
+        );
+
+        // Once expired the transaction can't run any more and is closed instead
+        set_clock(expires_at);
+        assert_eq!(
+            Processor::process(&program_id, &execute_accounts, &execute.data),
+            Err(VoteError::TransactionExpired.into())
+        );
+        Processor::process(&program_id, &close_accounts, &close.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.state, ProposalState::Defeated);
+        assert_eq!(state.outcome, Outcome::Passed);
+        assert_eq!(state.transaction_count, 0);
+
+        let close = close_receipt(&program_id, poll.key, voter.key, None);
+        Processor::process(&program_id, &[poll.clone(), receipt, voter], &close.data).unwrap();
+        let close = close_poll_account(&program_id, poll.key, creator.key, creator.key);
+        Processor::process(
+            &program_id,
+            &[poll.clone(), creator.clone(), creator.clone()],
+            &close.data,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_ranked_choice() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let init = |creator: &AccountInfo<'static>, vote_weight: VoteWeight, threshold| {
+            initialize_poll(
+                &program_id,
+                creator.key,
+                0,
+                "Team lead".into(),
+                "https://vote.hanmaster.ru/poll".into(),
+                [7; 32],
+                vec!["alice".into(), "bob".into(), "carol".into()],
+                10,
+                100,
+                vote_weight,
+                None,
+                Quorum::None,
+                threshold,
+                BallotType::RankedChoice,
+                None,
+            )
+        };
+
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        let init_accounts = [poll.clone(), creator.clone(), system_program_account()];
+        let instruction = init(
+            &creator,
+            VoteWeight::MerkleAllowlist { root: [1; 32] },
+            Threshold::SimpleMajority,
+        );
+        assert_eq!(
+            Processor::process(&program_id, &init_accounts, &instruction.data),
+            Err(VoteError::UnsupportedBallotType.into())
+        );
+        let instruction = init(&creator, VoteWeight::OnePerWallet, Threshold::TwoThirds);
+        assert_eq!(
+            Processor::process(&program_id, &init_accounts, &instruction.data),
+            Err(VoteError::InvalidThreshold.into())
+        );
+        let instruction = init(
+            &creator,
+            VoteWeight::OnePerWallet,
+            Threshold::SimpleMajority,
+        );
+        Processor::process(&program_id, &init_accounts, &instruction.data).unwrap();
+
+        set_clock(10);
+        let ballot_chunk = uncreated(find_ballot_chunk_address(&program_id, poll.key, 0).0);
+        let cast = |voter: &AccountInfo<'static>,
+                    receipt: &AccountInfo<'static>,
+                    ballot_count: u32,
+                    ranking: &[u8]| {
+            let instruction = cast_ranked_vote(
+                &program_id,
+                poll.key,
+                voter.key,
+                ballot_count,
+                None,
+                None,
+                ranking.to_vec(),
+            );
+            Processor::process(
+                &program_id,
+                &[
+                    poll.clone(),
+                    voter.clone(),
+                    receipt.clone(),
+                    system_program_account(),
+                    ballot_chunk.clone(),
+                ],
+                &instruction.data,
+            )
+        };
+
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        assert_eq!(
+            vote(&program_id, &poll, &voter, &receipt, 0),
+            Err(VoteError::UnsupportedBallotType.into())
+        );
+        assert_eq!(
+            cast(&voter, &receipt, 0, &[0, 1, 0]),
+            Err(VoteError::InvalidRanking.into())
+        );
+        assert_eq!(
+            cast(&voter, &receipt, 0, &[]),
+            Err(VoteError::InvalidRanking.into())
+        );
+        assert_eq!(
+            cast(&voter, &receipt, 0, &[0, 3]),
+            Err(VoteError::UnknownVoteOption.into())
+        );
+
+        let ballots: [&[u8]; 5] = [&[0, 1, 2], &[0, 1, 2], &[1, 2], &[1, 2], &[2, 0]];
+        let voters: Vec<_> = ballots
+            .iter()
+            .map(|_| {
+                let voter = wallet();
+                let receipt = receipt_for(&program_id, &poll, &voter);
+                (voter, receipt)
+            })
+            .collect();
+        for (ballot_count, ((voter, receipt), ranking)) in voters.iter().zip(ballots).enumerate() {
+            cast(voter, receipt, ballot_count as u32, ranking).unwrap();
+        }
+        let (voter, receipt) = &voters[0];
+        assert_eq!(
+            cast(voter, receipt, 5, &[2]),
+            Err(VoteError::AlreadyVoted.into())
+        );
+
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.ballot_count, 5);
+        let first_preferences: Vec<_> = state.options.iter().map(|option| option.votes).collect();
+        assert_eq!(first_preferences, [2, 2, 1]);
+        let chunk: BallotChunk = try_from_slice_unchecked(&ballot_chunk.data.borrow()).unwrap();
+        assert_eq!(chunk.ballots[4].ranking, [2, 0]);
+
+        let instruction = change_vote(&program_id, poll.key, voter.key, 1);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[poll.clone(), voter.clone(), receipt.clone()],
+                &instruction.data
+            ),
+            Err(VoteError::UnsupportedBallotType.into())
+        );
+
+        let payer = wallet();
+        let ranked_tally = uncreated(find_ranked_tally_address(&program_id, poll.key).0);
+        let run_tally = |chunks: &[&AccountInfo<'static>]| {
+            let mut accounts = vec![
+                poll.clone(),
+                ranked_tally.clone(),
+                payer.clone(),
+                system_program_account(),
+            ];
+            accounts.extend(chunks.iter().map(|chunk| (*chunk).clone()));
+            let instruction = tally(&program_id, poll.key, payer.key, &[]);
+            Processor::process(&program_id, &accounts, &instruction.data)
+        };
+        assert_eq!(run_tally(&[]), Err(VoteError::VotingNotEnded.into()));
+
+        // Each call counts as far as the ballot chunks passed allow
+        set_clock(100);
+        run_tally(&[]).unwrap();
+        let finalize_accounts = [poll.clone(), ranked_tally.clone()];
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+        assert_eq!(
+            Processor::process(&program_id, &finalize_accounts, &instruction.data),
+            Err(VoteError::TallyNotComplete.into())
+        );
+        let state: RankedTally = try_from_slice_unchecked(&ranked_tally.data.borrow()).unwrap();
+        assert_eq!(state.next_chunk, 0);
+        assert!(state.rounds.is_empty());
+
+        let foreign_chunk = uncreated(Pubkey::new_unique());
+        assert_eq!(
+            run_tally(&[&foreign_chunk]),
+            Err(ProgramError::InvalidSeeds)
+        );
+
+        run_tally(&[&ballot_chunk]).unwrap();
+        let state: RankedTally = try_from_slice_unchecked(&ranked_tally.data.borrow()).unwrap();
+        assert_eq!(state.rounds, [vec![2, 2, 1]]);
+        assert_eq!(state.eliminated, 0b100);
+        assert!(!state.is_complete);
+
+        run_tally(&[&ballot_chunk]).unwrap();
+        let state: RankedTally = try_from_slice_unchecked(&ranked_tally.data.borrow()).unwrap();
+        assert_eq!(state.rounds, [vec![2, 2, 1], vec![3, 2, 0]]);
+        assert_eq!(state.winner, Some(0));
+        assert!(state.is_complete);
+
+        assert_eq!(
+            run_tally(&[&ballot_chunk]),
+            Err(VoteError::TallyComplete.into())
+        );
+
+        // Alice lacks a first preference majority but wins the runoff
+        Processor::process(&program_id, &finalize_accounts, &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.outcome, Outcome::Passed);
+
+        let recipient = wallet();
+        let close = close_ballots(&program_id, poll.key, creator.key, recipient.key, &[0]);
+        let close_accounts = [
+            poll.clone(),
+            creator.clone(),
+            recipient.clone(),
+            ranked_tally.clone(),
+            ballot_chunk.clone(),
+        ];
+        let recipient_lamports =
+            recipient.lamports() + ranked_tally.lamports() + ballot_chunk.lamports();
+        Processor::process(&program_id, &close_accounts, &close.data).unwrap();
+        assert_eq!(recipient.lamports(), recipient_lamports);
+        assert_eq!((ranked_tally.lamports(), ballot_chunk.lamports()), (0, 0));
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.ballot_count, 0);
+        assert_eq!(
+            run_tally(&[&ballot_chunk]),
+            Err(VoteError::AlreadyFinalized.into())
+        );
+    }
+
+    #[test]
+    fn test_approval_vote() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let init = |creator: &AccountInfo<'static>, min_selections: u8, max_selections: u8| {
+            initialize_poll(
+                &program_id,
+                creator.key,
+                0,
+                "Wishlist".into(),
+                "https://vote.hanmaster.ru/poll".into(),
+                [7; 32],
+                vec![
+                    "dark mode".into(),
+                    "export".into(),
+                    "sso".into(),
+                    "api".into(),
+                ],
+                10,
+                100,
+                VoteWeight::OnePerWallet,
+                None,
+                Quorum::None,
+                Threshold::SimpleMajority,
+                BallotType::Approval {
+                    min_selections,
+                    max_selections,
+                },
+                None,
+            )
+        };
+
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        let init_accounts = [poll.clone(), creator.clone(), system_program_account()];
+        for (min_selections, max_selections) in [(0, 2), (3, 2), (1, 5)] {
+            assert_eq!(
+                Processor::process(
+                    &program_id,
+                    &init_accounts,
+                    &init(&creator, min_selections, max_selections).data
+                ),
+                Err(VoteError::InvalidSelectionCount.into())
+            );
+        }
+        let instruction = init(&creator, 1, 2);
+        Processor::process(&program_id, &init_accounts, &instruction.data).unwrap();
+
+        set_clock(10);
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let accounts = [
+            poll.clone(),
+            voter.clone(),
+            receipt.clone(),
+            system_program_account(),
+        ];
+        let approve = |options: &[u8]| {
+            let instruction = cast_approval_vote(
+                &program_id,
+                poll.key,
+                voter.key,
+                None,
+                None,
+                options.to_vec(),
+            );
+            Processor::process(&program_id, &accounts, &instruction.data)
+        };
+
+        assert_eq!(
+            vote(&program_id, &poll, &voter, &receipt, 0),
+            Err(VoteError::UnsupportedBallotType.into())
+        );
+        assert_eq!(approve(&[]), Err(VoteError::InvalidSelectionCount.into()));
+        assert_eq!(
+            approve(&[0, 1, 2]),
+            Err(VoteError::InvalidSelectionCount.into())
+        );
+        assert_eq!(approve(&[2, 2]), Err(VoteError::DuplicateSelection.into()));
+        assert_eq!(approve(&[4]), Err(VoteError::UnknownVoteOption.into()));
+
+        approve(&[3, 1]).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        let votes: Vec<_> = state.options.iter().map(|option| option.votes).collect();
+        assert_eq!(votes, [0, 1, 0, 1]);
+        let state = VoteReceipt::try_from_slice(&receipt.data.borrow()).unwrap();
+        assert_eq!(state.option, 1);
+        assert_eq!(state.selections, 0b1010);
+
+        let instruction = change_vote(&program_id, poll.key, voter.key, 0);
+        assert_eq!(
+            Processor::process(&program_id, &accounts[..3], &instruction.data),
+            Err(VoteError::UnsupportedBallotType.into())
+        );
+
+        let instruction = retract_vote(&program_id, poll.key, voter.key, None, &[]);
+        Processor::process(&program_id, &accounts[..3], &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert!(state.options.iter().all(|option| option.votes == 0));
+        assert_eq!(state.participating_weight, 0);
+    }
+
+    #[test]
+    fn test_approval_quorum() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        // One vote of weight one, against a quorum of two
+        let outcome = |ballot_type: BallotType, options: &[u8]| {
+            let creator = wallet();
+            let poll = new_poll(&program_id, &creator);
+            let instruction = initialize_poll(
+                &program_id,
+                creator.key,
+                0,
+                "Wishlist".into(),
+                "https://vote.hanmaster.ru/poll".into(),
+                [7; 32],
+                vec!["dark mode".into(), "export".into(), "sso".into()],
+                10,
+                100,
+                VoteWeight::OnePerWallet,
+                None,
+                Quorum::Absolute { votes: 2 },
+                Threshold::SimpleMajority,
+                ballot_type,
+                None,
+            );
+            Processor::process(
+                &program_id,
+                &[poll.clone(), creator, system_program_account()],
+                &instruction.data,
+            )
+            .unwrap();
+
+            set_clock(10);
+            let voter = wallet();
+            let receipt = receipt_for(&program_id, &poll, &voter);
+            let instruction = match ballot_type {
+                BallotType::Approval { .. } => cast_approval_vote(
+                    &program_id,
+                    poll.key,
+                    voter.key,
+                    None,
+                    None,
+                    options.to_vec(),
+                ),
+                _ => cast_vote(&program_id, poll.key, voter.key, None, None, options[0]),
+            };
+            Processor::process(
+                &program_id,
+                &[poll.clone(), voter, receipt, system_program_account()],
+                &instruction.data,
+            )
+            .unwrap();
+
+            set_clock(100);
+            let instruction = finalize(&program_id, poll.key, None, None, None);
+            Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data)
+                .unwrap();
+            let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+            assert_eq!(state.participating_weight, 1);
+            state.outcome
+        };
+
+        // Approving several options doesn't make the voter count more than once
+        let approval = BallotType::Approval {
+            min_selections: 1,
+            max_selections: 3,
+        };
+        assert_eq!(outcome(approval, &[0, 2]), Outcome::QuorumNotMet);
+        assert_eq!(
+            outcome(BallotType::SingleChoice, &[0]),
+            Outcome::QuorumNotMet
+        );
+    }
+
+    #[test]
+    fn test_quadratic_vote() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        let instruction = initialize_poll(
+            &program_id,
+            creator.key,
+            0,
+            "Budget".into(),
+            "https://vote.hanmaster.ru/poll".into(),
+            [7; 32],
+            vec!["docs".into(), "tooling".into(), "events".into()],
+            10,
+            100,
+            VoteWeight::OnePerWallet,
+            None,
+            Quorum::Absolute { votes: 2 },
+            Threshold::SimpleMajority,
+            BallotType::Quadratic { voice_credits: 10 },
+            None,
+        );
+        Processor::process(
+            &program_id,
+            &[poll.clone(), creator.clone(), system_program_account()],
+            &instruction.data,
+        )
+        .unwrap();
+
+        set_clock(10);
+        let voter = wallet();
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let accounts = [
+            poll.clone(),
+            voter.clone(),
+            receipt.clone(),
+            system_program_account(),
+        ];
+        let spend = |allocations: &[(u8, u64)]| {
+            let allocations = allocations
+                .iter()
+                .map(|(option, credits)| VoiceCreditAllocation {
+                    option: *option,
+                    credits: *credits,
+                })
+                .collect();
+            let instruction =
+                cast_quadratic_vote(&program_id, poll.key, voter.key, None, None, allocations);
+            Processor::process(&program_id, &accounts, &instruction.data)
+        };
+
+        assert_eq!(spend(&[]), Err(VoteError::InvalidSelectionCount.into()));
+        assert_eq!(
+            spend(&[(0, 9), (1, 0)]),
+            Err(VoteError::InvalidAllocation.into())
+        );
+        assert_eq!(
+            spend(&[(0, 9), (2, 2)]),
+            Err(VoteError::InsufficientVoiceCredits.into())
+        );
+        assert_eq!(
+            spend(&[(1, 4), (1, 4)]),
+            Err(VoteError::DuplicateSelection.into())
+        );
+        assert_eq!(spend(&[(3, 1)]), Err(VoteError::UnknownVoteOption.into()));
+
+        // 9 credits buy 3 votes, a single credit buys one
+        spend(&[(2, 1), (0, 9)]).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        let votes: Vec<_> = state.options.iter().map(|option| option.votes).collect();
+        assert_eq!(votes, [3, 0, 1]);
+        // The votes bought don't count towards the quorum, only the voter's weight does
+        assert_eq!(state.participating_weight, 1);
+        assert_eq!(state.tally_outcome(0), Outcome::QuorumNotMet);
+        let state = VoteReceipt::try_from_slice(&receipt.data.borrow()).unwrap();
+        assert_eq!(state.voice_credits_spent(), 10);
+        assert_eq!(state.selections, 0b101);
+
+        let instruction = retract_vote(&program_id, poll.key, voter.key, None, &[]);
+        Processor::process(&program_id, &accounts[..3], &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert!(state.options.iter().all(|option| option.votes == 0));
+
+        // A budget past u64::MAX is capped instead of shutting the voter out
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        let mint = Pubkey::new_unique();
+        let instruction = initialize_poll(
+            &program_id,
+            creator.key,
+            0,
+            "Budget".into(),
+            "https://vote.hanmaster.ru/poll".into(),
+            [7; 32],
+            vec!["docs".into(), "tooling".into()],
+            10,
+            100,
+            VoteWeight::Token { mint },
+            None,
+            Quorum::None,
+            Threshold::SimpleMajority,
+            BallotType::Quadratic { voice_credits: 10 },
+            None,
+        );
+        Processor::process(
+            &program_id,
+            &[poll.clone(), creator.clone(), system_program_account()],
+            &instruction.data,
+        )
+        .unwrap();
+        let whale = wallet();
+        let tokens = token_account(&mint, whale.key, u64::MAX / 2);
+        let allocations = vec![VoiceCreditAllocation {
+            option: 1,
+            credits: u64::MAX,
+        }];
+        let instruction = cast_quadratic_vote(
+            &program_id,
+            poll.key,
+            whale.key,
+            Some(tokens.key),
+            None,
+            allocations,
+        );
+        Processor::process(
+            &program_id,
+            &[
+                poll.clone(),
+                whale.clone(),
+                receipt_for(&program_id, &poll, &whale),
+                system_program_account(),
+                tokens,
+            ],
+            &instruction.data,
+        )
+        .unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[1].votes, 4294967295);
+
+        for (credits, votes) in [
+            (0, 0),
+            (1, 1),
+            (3, 1),
+            (15, 3),
+            (16, 4),
+            (u64::MAX, 4294967295),
+        ] {
+            assert_eq!(VoiceCreditAllocation { option: 0, credits }.votes(), votes);
+        }
+    }
+
+    #[test]
+    fn test_commit_reveal() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let init = |creator: &AccountInfo<'static>, reveal_end_ts: i64| {
+            initialize_poll(
+                &program_id,
+                creator.key,
+                0,
+                "Board seat".into(),
+                "https://vote.hanmaster.ru/poll".into(),
+                [7; 32],
+                vec!["alice".into(), "bob".into()],
+                10,
+                100,
+                VoteWeight::OnePerWallet,
+                None,
+                Quorum::None,
+                Threshold::SimpleMajority,
+                BallotType::SingleChoice,
+                Some(reveal_end_ts),
+            )
+        };
+
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        let init_accounts = [poll.clone(), creator.clone(), system_program_account()];
+        assert_eq!(
+            Processor::process(&program_id, &init_accounts, &init(&creator, 100).data),
+            Err(VoteError::InvalidVotingWindow.into())
+        );
+        Processor::process(&program_id, &init_accounts, &init(&creator, 200).data).unwrap();
+
+        set_clock(10);
+        let voters = [wallet(), wallet(), wallet()];
+        let receipts: Vec<_> = voters
+            .iter()
+            .map(|voter| receipt_for(&program_id, &poll, voter))
+            .collect();
+        let salts = [[1; 32], [2; 32], [3; 32]];
+        let options = [1, 1, 0];
+        for ((voter, receipt), (salt, option)) in
+            voters.iter().zip(&receipts).zip(salts.iter().zip(options))
+        {
+            let accounts = [
+                poll.clone(),
+                voter.clone(),
+                receipt.clone(),
+                system_program_account(),
+            ];
+            // Plain votes would be public
+            let instruction = cast_vote(&program_id, poll.key, voter.key, None, None, option);
+            assert_eq!(
+                Processor::process(&program_id, &accounts, &instruction.data),
+                Err(VoteError::UnsupportedBallotType.into())
+            );
+
+            let commitment = vote_commitment(option, salt, voter.key);
+            let instruction = commit_vote(&program_id, poll.key, voter.key, None, None, commitment);
+            Processor::process(&program_id, &accounts, &instruction.data).unwrap();
+        }
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.unrevealed_commits, 3);
+        assert!(state.options.iter().all(|option| option.votes == 0));
+        let state = try_from_slice_unchecked::<VoteReceipt>(&receipts[1].data.borrow()).unwrap();
+        assert_eq!((state.option, state.selections), (0, 0));
+
+        let reveal = |index: usize, option: u8, salt: [u8; 32]| {
+            let accounts = [poll.clone(), voters[index].clone(), receipts[index].clone()];
+            let instruction = reveal_vote(&program_id, poll.key, voters[index].key, option, salt);
+            Processor::process(&program_id, &accounts, &instruction.data)
+        };
+        assert_eq!(reveal(0, 1, salts[0]), Err(VoteError::RevealNotOpen.into()));
+
+        let accounts = [poll.clone(), voters[0].clone(), receipts[0].clone()];
+        let instruction = change_vote(&program_id, poll.key, voters[0].key, 0);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::UnsupportedBallotType.into())
+        );
+
+        set_clock(100);
+        assert_eq!(reveal(0, 0, salts[0]), Err(VoteError::InvalidReveal.into()));
+        assert_eq!(reveal(0, 1, salts[1]), Err(VoteError::InvalidReveal.into()));
+        reveal(0, 1, salts[0]).unwrap();
+        assert_eq!(reveal(0, 1, salts[0]), Err(VoteError::NotCommitted.into()));
+        reveal(2, 0, salts[2]).unwrap();
+
+        // Results are only final once revealing ends
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+        assert_eq!(
+            Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data),
+            Err(VoteError::VotingNotEnded.into())
+        );
+
+        set_clock(200);
+        assert_eq!(reveal(1, 1, salts[1]), Err(VoteError::RevealNotOpen.into()));
+        Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        let votes: Vec<_> = state.options.iter().map(|option| option.votes).collect();
+        assert_eq!(votes, [1, 1]);
+        assert_eq!(state.unrevealed_commits, 1);
+        let state = try_from_slice_unchecked::<VoteReceipt>(&receipts[0].data.borrow()).unwrap();
+        assert_eq!(
+            (state.option, state.selections, state.commitment),
+            (1, 0b10, None)
+        );
+    }
+
+    #[test]
+    fn test_delegation() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let creator = wallet();
+        let poll = new_poll(&program_id, &creator);
+        init_weighted_poll(
+            &program_id,
+            &poll,
+            &creator,
+            "Treasury",
+            &["fund", "reject"],
+            VoteWeight::Token { mint },
+        )
+        .unwrap();
+
+        let delegate_to = |delegator: &AccountInfo<'static>,
+                           delegatee: &Pubkey,
+                           scope: DelegationScope,
+                           delegation: &AccountInfo<'static>| {
+            let instruction = delegate(&program_id, delegator.key, delegatee, scope);
+            let accounts = [
+                delegator.clone(),
+                delegation.clone(),
+                system_program_account(),
+            ];
+            Processor::process(&program_id, &accounts, &instruction.data)
+        };
+        let delegation_for = |delegator: &AccountInfo<'static>, scope: &DelegationScope| {
+            uncreated(find_delegation_address(&program_id, delegator.key, scope).0)
+        };
+
+        let voter = wallet();
+        let (alice, bob, carol) = (wallet(), wallet(), wallet());
+        let alice_delegation = delegation_for(&alice, &DelegationScope::All);
+        let bob_scope = DelegationScope::Poll { poll: *poll.key };
+        let bob_delegation = delegation_for(&bob, &bob_scope);
+        let carol_delegation = delegation_for(&carol, &DelegationScope::All);
+        assert_eq!(
+            delegate_to(&alice, alice.key, DelegationScope::All, &alice_delegation),
+            Err(VoteError::InvalidDelegation.into())
+        );
+        // Delegating again replaces the delegate
+        delegate_to(&alice, carol.key, DelegationScope::All, &alice_delegation).unwrap();
+        delegate_to(&alice, voter.key, DelegationScope::All, &alice_delegation).unwrap();
+        delegate_to(&bob, voter.key, bob_scope, &bob_delegation).unwrap();
+        delegate_to(&carol, voter.key, DelegationScope::All, &carol_delegation).unwrap();
+        let state =
+            try_from_slice_unchecked::<Delegation>(&alice_delegation.data.borrow()).unwrap();
+        assert_eq!(state.delegate, *voter.key);
+
+        set_clock(10);
+        let tokens =
+            |owner: &AccountInfo<'static>, amount: u64| token_account(&mint, owner.key, amount);
+        let (alice_tokens, bob_tokens, carol_tokens) =
+            (tokens(&alice, 200), tokens(&bob, 300), tokens(&carol, 400));
+        let alice_receipt = receipt_for(&program_id, &poll, &alice);
+        let bob_receipt = receipt_for(&program_id, &poll, &bob);
+        let carol_receipt = receipt_for(&program_id, &poll, &carol);
+
+        // Carol votes directly before the delegate gets to use the weight
+        vote_with_tokens(&program_id, &poll, &carol, &carol_receipt, &carol_tokens, 1).unwrap();
+
+        let receipt = receipt_for(&program_id, &poll, &voter);
+        let vote_for = |delegations: &[(
+            &AccountInfo<'static>,
+            &AccountInfo<'static>,
+            &AccountInfo<'static>,
+        )]| {
+            let mut accounts = vec![
+                poll.clone(),
+                voter.clone(),
+                receipt.clone(),
+                system_program_account(),
+                tokens(&voter, 100),
+            ];
+            for (delegation, delegator_receipt, delegator_tokens) in delegations {
+                accounts.extend([
+                    (*delegation).clone(),
+                    (*delegator_receipt).clone(),
+                    (*delegator_tokens).clone(),
+                ]);
+            }
+            let instruction = cast_vote(&program_id, poll.key, voter.key, None, None, 0);
+            Processor::process(&program_id, &accounts, &instruction.data)
+        };
+        assert_eq!(
+            vote_for(&[(&carol_delegation, &carol_receipt, &carol_tokens)]),
+            Err(VoteError::AlreadyVoted.into())
+        );
+        assert_eq!(
+            vote_for(&[(&bob_delegation, &alice_receipt, &bob_tokens)]),
+            Err(ProgramError::InvalidSeeds)
+        );
+        vote_for(&[
+            (&alice_delegation, &alice_receipt, &alice_tokens),
+            (&bob_delegation, &bob_receipt, &bob_tokens),
+        ])
+        .unwrap();
+
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        let votes: Vec<_> = state.options.iter().map(|option| option.votes).collect();
+        assert_eq!(votes, [600, 400]);
+        assert_eq!(state.open_receipts, 4);
+        let state = try_from_slice_unchecked::<VoteReceipt>(&alice_receipt.data.borrow()).unwrap();
+        assert_eq!((state.weight, state.delegate), (200, Some(*voter.key)));
+
+        // Delegators can't vote on their own any more
+        assert_eq!(
+            vote_with_tokens(&program_id, &poll, &alice, &alice_receipt, &alice_tokens, 1),
+            Err(VoteError::AlreadyVoted.into())
+        );
+        let instruction = retract_vote(&program_id, poll.key, alice.key, None, &[]);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[poll.clone(), alice.clone(), alice_receipt.clone()],
+                &instruction.data
+            ),
+            Err(VoteError::VoteDelegated.into())
+        );
+        let instruction = change_vote(&program_id, poll.key, alice.key, 1);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[poll.clone(), alice.clone(), alice_receipt.clone()],
+                &instruction.data
+            ),
+            Err(VoteError::VoteDelegated.into())
+        );
+
+        // Retracting takes the delegated weight along and frees the delegators
+        let retract_accounts = [poll.clone(), voter.clone(), receipt.clone()];
+        let instruction = retract_vote(&program_id, poll.key, voter.key, None, &[*alice.key]);
+        assert_eq!(
+            Processor::process(&program_id, &retract_accounts, &instruction.data),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        let instruction = retract_vote(
+            &program_id,
+            poll.key,
+            voter.key,
+            None,
+            &[*alice.key, *bob.key],
+        );
+        let mut accounts = retract_accounts.to_vec();
+        accounts.extend([carol_receipt.clone(), bob_receipt.clone()]);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::InvalidDelegation.into())
+        );
+        let mut accounts = retract_accounts.to_vec();
+        accounts.extend([alice_receipt.clone(), bob_receipt.clone()]);
+        Processor::process(&program_id, &accounts, &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        let votes: Vec<_> = state.options.iter().map(|option| option.votes).collect();
+        assert_eq!(votes, [0, 400]);
+        assert_eq!(state.open_receipts, 1);
+        assert_eq!(bob_receipt.lamports(), 0);
+        vote_with_tokens(&program_id, &poll, &alice, &alice_receipt, &alice_tokens, 1).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.options[1].votes, 600);
+
+        // Bob's delegation only covers this poll
+        let other_creator = wallet();
+        let other_poll = new_poll(&program_id, &other_creator);
+        init_weighted_poll(
+            &program_id,
+            &other_poll,
+            &other_creator,
+            "Budget",
+            &["fund", "reject"],
+            VoteWeight::Token { mint },
+        )
+        .unwrap();
+        let other_receipt = receipt_for(&program_id, &other_poll, &bob);
+        let delegated = [bob_delegation.clone(), other_receipt, bob_tokens.clone()];
+        let metas = delegator_accounts(
+            &program_id,
+            other_poll.key,
+            bob.key,
+            &bob_scope,
+            Some(bob_tokens.key),
+            None,
+        );
+        assert!(metas
+            .iter()
+            .zip(&delegated)
+            .all(|(meta, account)| meta.pubkey == *account.key));
+        let mut accounts = vec![
+            other_poll.clone(),
+            voter.clone(),
+            receipt_for(&program_id, &other_poll, &voter),
+            system_program_account(),
+            tokens(&voter, 100),
+        ];
+        accounts.extend(delegated);
+        let instruction = cast_vote(&program_id, other_poll.key, voter.key, None, None, 0);
+        assert_eq!(
+            Processor::process(&program_id, &accounts, &instruction.data),
+            Err(VoteError::InvalidDelegation.into())
+        );
+
+        let revoke = |delegator: &AccountInfo<'static>| {
+            let instruction = revoke_delegation(&program_id, delegator.key, &DelegationScope::All);
+            Processor::process(
+                &program_id,
+                &[delegator.clone(), alice_delegation.clone()],
+                &instruction.data,
+            )
+        };
+        assert_eq!(revoke(&carol), Err(VoteError::InvalidAuthority.into()));
+        revoke(&alice).unwrap();
+        assert_eq!(alice_delegation.lamports(), 0);
+    }
+
+    #[test]
+    fn test_realm() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let authority = wallet();
+        let create = |name: &str, voting_duration: u32| {
+            let realm = uncreated(find_realm_address(&program_id, name).0);
+            let instruction = create_realm(
+                &program_id,
+                authority.key,
+                name.into(),
+                &mint,
+                voting_duration,
+                Quorum::Percent { percent: 10 },
+                Threshold::TwoThirds,
+                0,
+                0,
+            );
+            let accounts = [realm.clone(), authority.clone(), system_program_account()];
+            Processor::process(&program_id, &accounts, &instruction.data).map(|_| realm)
+        };
+        assert_eq!(
+            create("", 90).err(),
+            Some(VoteError::InvalidRealmName.into())
+        );
+        assert_eq!(
+            create("Hanmaster", 0).err(),
+            Some(VoteError::InvalidVotingWindow.into())
+        );
+        let realm = create("Hanmaster", 90).unwrap();
+
+        let propose = |creator: &AccountInfo<'static>, index: u64, poll: &AccountInfo<'static>| {
+            let instruction = create_proposal(
+                &program_id,
+                realm.key,
+                creator.key,
+                index,
+                "Fund the docs".into(),
+                "https://vote.hanmaster.ru/proposal".into(),
+                [7; 32],
+                vec!["yes".into(), "no".into()],
+                10,
+                BallotType::SingleChoice,
+                None,
+            );
+            let accounts = [
+                realm.clone(),
+                poll.clone(),
+                creator.clone(),
+                system_program_account(),
+            ];
+            Processor::process(&program_id, &accounts, &instruction.data)
+        };
+        let proposals: Vec<_> = (0..2)
+            .map(|index| uncreated(find_poll_address(&program_id, realm.key, index).0))
+            .collect();
+        let creators = [wallet(), wallet()];
+        assert_eq!(
+            propose(&creators[0], 1, &proposals[1]),
+            Err(ProgramError::InvalidSeeds)
+        );
+        for (index, (creator, poll)) in creators.iter().zip(&proposals).enumerate() {
+            propose(creator, index as u64, poll).unwrap();
+        }
+
+        let state = try_from_slice_unchecked::<Realm>(&realm.data.borrow()).unwrap();
+        assert_eq!(state.proposal_count, 2);
+        assert_eq!(state.authority, *authority.key);
+        let state = Poll::try_from_slice(&proposals[1].data.borrow()).unwrap();
+        assert_eq!(state.authority, *creators[1].key);
+        assert_eq!((state.poll_id, state.realm), (1, Some(*realm.key)));
+        assert_eq!((state.start_ts, state.end_ts), (10, 100));
+        assert_eq!(state.vote_weight, VoteWeight::Escrow { mint });
+        assert_eq!(state.quorum, Quorum::Percent { percent: 10 });
+        assert_eq!(state.threshold, Threshold::TwoThirds);
+        assert_eq!(state.governance(), realm.key);
+
+        // Only cancelling ends a proposal early
+        let instruction = close_poll(&program_id, proposals[1].key, creators[1].key);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[proposals[1].clone(), creators[1].clone()],
+                &instruction.data
+            ),
+            Err(VoteError::InvalidProposalState.into())
+        );
+    }
+
+    #[test]
+    fn test_proposal_deposit() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let deposit = 5_000_000;
+        let authority = wallet();
+        let realm = uncreated(find_realm_address(&program_id, "Hanmaster").0);
+        let instruction = create_realm(
+            &program_id,
+            authority.key,
+            "Hanmaster".into(),
+            &mint,
+            90,
+            Quorum::None,
+            Threshold::SimpleMajority,
+            50,
+            deposit,
+        );
+        Processor::process(
+            &program_id,
+            &[realm.clone(), authority.clone(), system_program_account()],
+            &instruction.data,
+        )
+        .unwrap();
+
+        let escrow_for = |owner: &AccountInfo<'static>, amount: u64| {
+            let escrow = VoterEscrow {
+                account_type: AccountType::VoterEscrow,
+                mint,
+                voter: *owner.key,
+                amount,
+                active_votes: 0,
+                active_proposals: 0,
+            };
+            program_account(
+                find_escrow_address(&program_id, &mint, owner.key).0,
+                program_id,
+                escrow.try_to_vec().unwrap(),
+            )
+        };
+        let proposals: Vec<_> = (0..3)
+            .map(|index| uncreated(find_poll_address(&program_id, realm.key, index).0))
+            .collect();
+        let propose = |creator: &AccountInfo<'static>, escrow: Option<&AccountInfo<'static>>| {
+            let index = try_from_slice_unchecked::<Realm>(&realm.data.borrow())
+                .unwrap()
+                .proposal_count;
+            let instruction = create_proposal(
+                &program_id,
+                realm.key,
+                creator.key,
+                index,
+                "Fund the docs".into(),
+                "https://vote.hanmaster.ru/proposal".into(),
+                [7; 32],
+                vec!["yes".into(), "no".into()],
+                10,
+                BallotType::SingleChoice,
+                escrow.map(|escrow| escrow.key),
+            );
+            let mut accounts = vec![
+                realm.clone(),
+                proposals[index as usize].clone(),
+                creator.clone(),
+                system_program_account(),
+            ];
+            accounts.extend(escrow.cloned());
+            Processor::process(&program_id, &accounts, &instruction.data)
+        };
+
+        let creator = wallet();
+        assert_eq!(
+            propose(&creator, None),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        assert_eq!(
+            propose(&creator, Some(&escrow_for(&creator, 49))),
+            Err(VoteError::InsufficientProposalWeight.into())
+        );
+        assert_eq!(
+            propose(&creator, Some(&escrow_for(&wallet(), 50))),
+            Err(VoteError::InvalidVoterEscrow.into())
+        );
+        let escrow = escrow_for(&creator, 50);
+        for _ in 0..3 {
+            propose(&creator, Some(&escrow)).unwrap();
+        }
+        let rent = Rent::default().minimum_balance(proposals[0].data_len());
+        assert_eq!(proposals[0].lamports(), rent + deposit);
+        let active_proposals = || {
+            VoterEscrow::try_from_slice(&escrow.data.borrow())
+                .unwrap()
+                .active_proposals
+        };
+        assert_eq!(active_proposals(), 3);
+
+        // The escrow backs the proposals until they are resolved, it can't be withdrawn to
+        // back another creator's proposals meanwhile
+        let vault = uncreated(find_escrow_vault_address(&program_id, &mint, creator.key).0);
+        let instruction = withdraw(&program_id, &mint, creator.key, vault.key, 50);
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                &[
+                    escrow.clone(),
+                    vault.clone(),
+                    creator.clone(),
+                    vault,
+                    program_account(spl_token::id(), Pubkey::default(), vec![]),
+                ],
+                &instruction.data
+            ),
+            Err(VoteError::EscrowLocked.into())
+        );
+
+        // The creator withdraws the first proposal and gets the deposit back
+        let creator_lamports = creator.lamports();
+        let cancel = |poll: &AccountInfo<'static>, signer: &AccountInfo<'static>, spam: bool| {
+            let realm_key = spam.then_some(realm.key);
+            let instruction = cancel_proposal(
+                &program_id,
+                poll.key,
+                signer.key,
+                realm_key,
+                Some(escrow.key),
+            );
+            let mut accounts = vec![poll.clone(), signer.clone()];
+            if spam {
+                accounts.push(realm.clone());
+                accounts.push(uncreated(find_treasury_address(&program_id, realm.key).0));
+            }
+            accounts.push(escrow.clone());
+            Processor::process(&program_id, &accounts, &instruction.data).map(|_| accounts)
+        };
+        cancel(&proposals[0], &creator, false).unwrap();
+        assert_eq!(active_proposals(), 2);
+        assert_eq!(creator.lamports(), creator_lamports + deposit);
+        assert_eq!(proposals[0].lamports(), rent);
+        let state = Poll::try_from_slice(&proposals[0].data.borrow()).unwrap();
+        assert_eq!((state.outcome, state.deposit), (Outcome::Cancelled, 0));
+        assert_eq!(
+            cancel(&proposals[0], &creator, false).err(),
+            Some(VoteError::AlreadyFinalized.into())
+        );
+
+        // Only the realm authority may cancel someone else's proposal, forfeiting the deposit
+        assert_eq!(
+            cancel(&proposals[1], &wallet(), true).err(),
+            Some(VoteError::InvalidAuthority.into())
+        );
+        let accounts = cancel(&proposals[1], &authority, true).unwrap();
+        assert_eq!(accounts[3].lamports(), deposit);
+        assert_eq!(creator.lamports(), creator_lamports + deposit);
+
+        // Finalizing refunds the deposit
+        let instruction = sign_off(&program_id, proposals[2].key, creator.key);
+        Processor::process(
+            &program_id,
+            &[proposals[2].clone(), creator.clone()],
+            &instruction.data,
+        )
+        .unwrap();
+        set_clock(100);
+        assert_eq!(
+            cancel(&proposals[2], &creator, false).err(),
+            Some(VoteError::Ended.into())
+        );
+        let instruction = finalize(
+            &program_id,
+            proposals[2].key,
+            None,
+            Some(creator.key),
+            Some(escrow.key),
+        );
+        assert_eq!(
+            Processor::process(
+                &program_id,
+                std::slice::from_ref(&proposals[2]),
+                &instruction.data
+            ),
+            Err(ProgramError::NotEnoughAccountKeys)
+        );
+        Processor::process(
+            &program_id,
+            &[proposals[2].clone(), creator.clone(), escrow.clone()],
+            &instruction.data,
+        )
+        .unwrap();
+        assert_eq!(active_proposals(), 0);
+        assert_eq!(creator.lamports(), creator_lamports + 2 * deposit);
+        let state = Poll::try_from_slice(&proposals[2].data.borrow()).unwrap();
+        assert_eq!((state.outcome, state.deposit), (Outcome::Rejected, 0));
+        assert_eq!(state.state, ProposalState::Defeated);
+    }
+
+    #[test]
+    fn test_proposal_lifecycle() {
+        setup();
+        let program_id = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let authority = wallet();
+        let realm = uncreated(find_realm_address(&program_id, "Hanmaster").0);
+        let instruction = create_realm(
+            &program_id,
+            authority.key,
+            "Hanmaster".into(),
+            &mint,
+            90,
+            Quorum::None,
+            Threshold::SimpleMajority,
+            0,
+            0,
+        );
+        Processor::process(
+            &program_id,
+            &[realm.clone(), authority.clone(), system_program_account()],
+            &instruction.data,
+        )
+        .unwrap();
+
+        set_clock(0);
+        let creator = wallet();
+        let poll = uncreated(find_poll_address(&program_id, realm.key, 0).0);
+        let instruction = create_proposal(
+            &program_id,
+            realm.key,
+            creator.key,
+            0,
+            "Fund the docs".into(),
+            "https://vote.hanmaster.ru/proposal".into(),
+            [7; 32],
+            vec!["yes".into(), "no".into()],
+            10,
+            BallotType::SingleChoice,
+            None,
+        );
+        Processor::process(
+            &program_id,
+            &[
+                realm.clone(),
+                poll.clone(),
+                creator.clone(),
+                system_program_account(),
+            ],
+            &instruction.data,
+        )
+        .unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.state, ProposalState::Draft);
+
+        // Drafts take transactions past their planned start, but no votes
+        set_clock(50);
+        let (treasury_key, _) = find_treasury_address(&program_id, realm.key);
+        let treasury = program_account(treasury_key, system_program::id(), vec![]);
+        let recipient = wallet();
+        let instructions: Vec<InstructionData> =
+            vec![system_instruction::transfer(treasury.key, recipient.key, 1_000).into()];
+        let proposal_transaction =
+            uncreated(find_proposal_transaction_address(&program_id, poll.key, 0).0);
+        let instruction = insert_transaction(
+            &program_id,
+            poll.key,
+            creator.key,
+            0,
+            0,
+            instructions.clone(),
+        );
+        Processor::process(
+            &program_id,
+            &[
+                poll.clone(),
+                creator.clone(),
+                proposal_transaction.clone(),
+                system_program_account(),
+            ],
+            &instruction.data,
+        )
+        .unwrap();
+
+        let voter = wallet();
+        let escrow = program_account(
+            find_escrow_address(&program_id, &mint, voter.key).0,
+            program_id,
+            VoterEscrow {
+                account_type: AccountType::VoterEscrow,
+                mint,
+                voter: *voter.key,
+                amount: 700,
+                active_votes: 0,
+                active_proposals: 0,
+            }
+            .try_to_vec()
+            .unwrap(),
+        );
+        let vote_accounts = [
+            poll.clone(),
+            voter.clone(),
+            receipt_for(&program_id, &poll, &voter),
+            system_program_account(),
+            escrow.clone(),
+        ];
+        let instruction = cast_escrow_vote(&program_id, poll.key, voter.key, &mint, None, 0);
+        assert_eq!(
+            Processor::process(&program_id, &vote_accounts, &instruction.data),
+            Err(VoteError::InvalidProposalState.into())
+        );
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+        assert_eq!(
+            Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data),
+            Err(VoteError::InvalidProposalState.into())
+        );
+
+        // Signing off late still leaves the full voting duration
+        let sign_off_as = |signer: &AccountInfo<'static>| {
+            let instruction = sign_off(&program_id, poll.key, signer.key);
+            Processor::process(
+                &program_id,
+                &[poll.clone(), signer.clone()],
+                &instruction.data,
+            )
+        };
+        assert_eq!(
+            sign_off_as(&authority),
+            Err(VoteError::InvalidAuthority.into())
+        );
+        sign_off_as(&creator).unwrap();
+        assert_eq!(
+            sign_off_as(&creator),
+            Err(VoteError::InvalidProposalState.into())
+        );
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.state, ProposalState::Voting);
+        assert_eq!((state.start_ts, state.end_ts), (50, 140));
+        assert_eq!(state.transaction_count, 1);
+
+        let instruction = cast_escrow_vote(&program_id, poll.key, voter.key, &mint, None, 0);
+        Processor::process(&program_id, &vote_accounts, &instruction.data).unwrap();
+
+        set_clock(140);
+        let instruction = finalize(&program_id, poll.key, None, None, None);
+        Processor::process(&program_id, std::slice::from_ref(&poll), &instruction.data).unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.state, ProposalState::Succeeded);
+
+        // Executing the last transaction completes the proposal
+        let instruction = execute_proposal(&program_id, poll.key, realm.key, 0, &instructions);
+        Processor::process(
+            &program_id,
+            &[
+                poll.clone(),
+                proposal_transaction,
+                treasury,
+                system_program_account(),
+                recipient,
+            ],
+            &instruction.data,
+        )
+        .unwrap();
+        let state = Poll::try_from_slice(&poll.data.borrow()).unwrap();
+        assert_eq!(state.state, ProposalState::Executed);
+        assert_eq!(state.executed_transaction_count, 1);
+        assert_eq!(
+            sign_off_as(&creator),
+            Err(VoteError::InvalidProposalState.into())
+        );
+    }
+}
@@ -0,0 +1,832 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    keccak,
+    pubkey::Pubkey,
+};
+
+use crate::error::VoteError;
+
+/// Seed prefix of the poll program derived addresses
+pub const POLL_SEED: &[u8] = b"poll";
+
+/// Seed prefix of the vote receipt program derived addresses
+pub const RECEIPT_SEED: &[u8] = b"receipt";
+
+/// Seed prefix of the voter escrow program derived addresses
+pub const ESCROW_SEED: &[u8] = b"escrow";
+
+/// Seed prefix of the token accounts holding escrowed governance tokens
+pub const ESCROW_VAULT_SEED: &[u8] = b"escrow-vault";
+
+/// Seed prefix of the voter registry program derived addresses
+pub const REGISTRY_SEED: &[u8] = b"registry";
+
+/// Seed prefix of the registry membership program derived addresses
+pub const MEMBER_SEED: &[u8] = b"member";
+
+/// Seed prefix of the proposal transaction program derived addresses
+pub const PROPOSAL_TRANSACTION_SEED: &[u8] = b"proposal-transaction";
+
+/// Seed prefix of the governance treasury program derived addresses
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// Seed prefix of the ranked ballot chunk program derived addresses
+pub const BALLOT_CHUNK_SEED: &[u8] = b"ballots";
+
+/// Seed prefix of the ranked tally program derived addresses
+pub const RANKED_TALLY_SEED: &[u8] = b"ranked-tally";
+
+/// Seed prefix of the vote delegation program derived addresses
+pub const DELEGATION_SEED: &[u8] = b"delegation";
+
+/// Seed prefix of the realm program derived addresses
+pub const REALM_SEED: &[u8] = b"realm";
+
+/// Ranked ballots stored per ballot chunk
+pub const BALLOTS_PER_CHUNK: u32 = 32;
+
+/// Minimum number of options a poll can be created with
+pub const MIN_OPTIONS: usize = 2;
+
+/// Maximum number of options a poll can be created with
+pub const MAX_OPTIONS: usize = 20;
+
+/// Maximum length of an option label in bytes
+pub const MAX_OPTION_LABEL_LEN: usize = 32;
+
+/// Maximum length of a poll title in bytes
+pub const MAX_TITLE_LEN: usize = 64;
+
+/// Maximum length of a poll description URI in bytes
+pub const MAX_DESCRIPTION_URI_LEN: usize = 200;
+
+/// Maximum length of a realm name in bytes, the name seeds the realm address
+pub const MAX_REALM_NAME_LEN: usize = 32;
+
+/// Seconds a proposal transaction stays executable after its hold up time, a week
+pub const EXECUTION_GRACE_PERIOD: i64 = 7 * 24 * 60 * 60;
+
+/// Leading byte of every program account, telling which layout the rest of the data uses.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum AccountType {
+    #[default]
+    Uninitialized,
+    /// Deprecated, never assigned to an account
+    DeprecatedPollV1,
+    Poll,
+    VoterEscrow,
+    VoterRegistry,
+    RegistryMember,
+    /// Tombstone of a closed account, whatever followed it has been zeroed
+    Closed,
+    ProposalTransaction,
+    BallotChunk,
+    RankedTally,
+    Delegation,
+    Realm,
+    VoteReceipt,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct VoteOption {
+    pub label: String,
+    pub votes: u64,
+}
+
+impl VoteOption {
+    pub fn add_votes(&mut self, votes: u64) -> Result<(), VoteError> {
+        self.votes = self
+            .votes
+            .checked_add(votes)
+            .ok_or(VoteError::CounterOverflow)?;
+        Ok(())
+    }
+
+    pub fn remove_votes(&mut self, votes: u64) -> Result<(), VoteError> {
+        self.votes = self
+            .votes
+            .checked_sub(votes)
+            .ok_or(VoteError::CounterOverflow)?;
+        Ok(())
+    }
+}
+
+/// How much a single vote counts towards the tally.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum VoteWeight {
+    /// Every voter adds one vote
+    #[default]
+    OnePerWallet,
+    /// Every voter adds the balance of their token account of the governance mint
+    Token { mint: Pubkey },
+    /// Every voter adds the governance tokens they locked in their voter escrow
+    Escrow { mint: Pubkey },
+    /// Only voters with a leaf in the Merkle tree may vote, adding the weight of their
+    /// leaf, see [`crate::merkle`]
+    MerkleAllowlist { root: [u8; 32] },
+}
+
+/// Minimum participation for a poll to be decided, counting the weight of every vote
+/// cast once.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub enum Quorum {
+    #[default]
+    None,
+    /// At least this many votes
+    Absolute { votes: u64 },
+    /// At least this percentage of the total weight: the governance mint supply in token
+    /// and escrow weighted polls, otherwise the voter registry's member count
+    Percent { percent: u8 },
+}
+
+/// Share of the votes option 0 ("yes") needs to pass. Option 1 counts as "no", any other
+/// option as an abstention. Ranked choice polls pass when option 0 wins the instant-runoff,
+/// which always takes a simple majority of the final round.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Threshold {
+    /// More than half of all votes
+    #[default]
+    SimpleMajority,
+    /// At least two thirds of all votes
+    TwoThirds,
+    /// More than half of the yes and no votes, abstentions are left out
+    MajorityOfYesNo,
+}
+
+/// What a single ballot of the poll expresses.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum BallotType {
+    /// A vote for one option
+    #[default]
+    SingleChoice,
+    /// Options ordered by preference, decided by instant-runoff with `Tally`
+    RankedChoice,
+    /// A vote for every option the voter supports, each selected option gets the full
+    /// weight of the vote
+    Approval {
+        min_selections: u8,
+        max_selections: u8,
+    },
+    /// Voice credits spread over any number of options, `k` votes for an option cost `k²`
+    /// credits. Each voter gets `voice_credits` per unit of vote weight. Quorums count
+    /// that weight, not the votes bought with it.
+    Quadratic { voice_credits: u64 },
+}
+
+/// Result of a poll, decided by `Finalize`.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Outcome {
+    #[default]
+    Pending,
+    Passed,
+    Rejected,
+    QuorumNotMet,
+    /// Withdrawn by the creator, or by the realm authority as spam, or closed early
+    Cancelled,
+}
+
+/// Stage of a poll's lifecycle, every instruction checks it before acting. Standalone polls
+/// start out voting, realm proposals as drafts their creator signs off.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProposalState {
+    /// Transactions can be inserted, votes are not accepted yet
+    #[default]
+    Draft,
+    /// Votes are accepted within the voting window
+    Voting,
+    /// Finalized as passed, the proposal transactions can be executed
+    Succeeded,
+    /// Finalized without passing
+    Defeated,
+    /// Every proposal transaction has been executed
+    Executed,
+    /// Withdrawn before the tally was final
+    Cancelled,
+}
+
+/// A poll and its tally.
+///
+/// Every variable length field is fixed at creation, so the account is sized from the
+/// initial state.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct Poll {
+    pub account_type: AccountType,
+    /// The only key allowed to manage the poll, the creator of the poll
+    pub authority: Pubkey,
+    pub poll_id: u64,
+    pub title: String,
+    /// Off-chain location of the full poll description
+    pub description_uri: String,
+    /// SHA-256 hash of the content behind `description_uri`
+    pub content_hash: [u8; 32],
+    pub options: Vec<VoteOption>,
+    /// Unix timestamp voting opens at
+    pub start_ts: i64,
+    /// Unix timestamp voting ends at, votes are accepted strictly before it
+    pub end_ts: i64,
+    pub vote_weight: VoteWeight,
+    /// Registry the voters must be members of, anyone may vote if unset
+    pub voter_registry: Option<Pubkey>,
+    /// Vote receipts that have not been closed yet
+    pub open_receipts: u64,
+    /// Weight of the votes counted in the tally, once per vote however many options it
+    /// counts for. Quorums are measured against it.
+    pub participating_weight: u64,
+    pub quorum: Quorum,
+    pub threshold: Threshold,
+    pub outcome: Outcome,
+    pub state: ProposalState,
+    pub ballot_type: BallotType,
+    /// Ranked ballots cast, stored in ballot chunks of `BALLOTS_PER_CHUNK`. Drops as the
+    /// chunks are closed after finalization.
+    pub ballot_count: u32,
+    /// Commit-reveal polls only: Unix timestamp revealing ends at. Votes are committed
+    /// until `end_ts` and revealed from then on.
+    pub reveal_end_ts: Option<i64>,
+    /// Committed votes that have not been revealed, they don't count towards the tally
+    pub unrevealed_commits: u64,
+    /// Realm the poll is a proposal of, `poll_id` being its proposal index
+    pub realm: Option<Pubkey>,
+    /// Lamports the creator locked in the account on top of rent, refunded once the
+    /// proposal is finalized or cancelled by the creator
+    pub deposit: u64,
+    /// Realm proposals only: the creator's escrow, locked until the proposal is finalized
+    /// or cancelled if the realm requires a minimum proposal weight
+    pub creator_escrow: Option<Pubkey>,
+    /// Proposal transactions inserted and not closed yet
+    pub transaction_count: u16,
+    /// Proposal transactions executed, the proposal is executed once all of them are
+    pub executed_transaction_count: u16,
+    pub is_closed: bool,
+    /// Set once the voting window has passed, the tally can no longer change
+    pub is_finalized: bool,
+}
+
+impl Poll {
+    /// Ends the poll for good without an outcome, its votes can't make it pass any more.
+    pub fn cancel(&mut self) {
+        self.outcome = Outcome::Cancelled;
+        self.state = ProposalState::Cancelled;
+        self.is_closed = true;
+        self.is_finalized = true;
+    }
+
+    /// Key the governance treasury of the poll is derived from: the realm of proposals,
+    /// the authority of standalone polls.
+    pub fn governance(&self) -> &Pubkey {
+        self.realm.as_ref().unwrap_or(&self.authority)
+    }
+
+    /// Unix timestamp the tally can no longer change from, the end of revealing in
+    /// commit-reveal polls.
+    pub fn tally_end_ts(&self) -> i64 {
+        self.reveal_end_ts.unwrap_or(self.end_ts)
+    }
+
+    /// Outcome of the current tally, `total_weight` is only needed for percentage quorums.
+    pub fn tally_outcome(&self, total_weight: u64) -> Outcome {
+        let votes_of = |index: usize| {
+            self.options
+                .get(index)
+                .map_or(0, |option| u128::from(option.votes))
+        };
+        let all: u128 = self
+            .options
+            .iter()
+            .map(|option| u128::from(option.votes))
+            .sum();
+        let (yes, no) = (votes_of(0), votes_of(1));
+
+        let participating = u128::from(self.participating_weight);
+        let quorum_met = match self.quorum {
+            Quorum::None => true,
+            Quorum::Absolute { votes } => participating >= u128::from(votes),
+            Quorum::Percent { percent } => {
+                participating * 100 >= u128::from(total_weight) * u128::from(percent)
+            }
+        };
+        if !quorum_met {
+            return Outcome::QuorumNotMet;
+        }
+
+        let passed = match self.threshold {
+            Threshold::SimpleMajority => yes * 2 > all,
+            Threshold::TwoThirds => yes > 0 && yes * 3 >= all * 2,
+            Threshold::MajorityOfYesNo => yes > no,
+        };
+        if passed {
+            Outcome::Passed
+        } else {
+            Outcome::Rejected
+        }
+    }
+}
+
+/// Proof that a voter has cast a vote in a poll.
+///
+/// Lives at the program derived address of (poll, voter), so its existence alone
+/// prevents the same wallet from voting twice.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct VoteReceipt {
+    pub account_type: AccountType,
+    pub poll: Pubkey,
+    pub voter: Pubkey,
+    /// The first option the vote counts for, 0 while the vote is committed or delegated
+    pub option: u8,
+    /// Votes added to the tally of every option the vote counts for
+    pub weight: u64,
+    /// Options the vote counts for, bit `i` for option `i`
+    pub selections: u32,
+    /// Voice credits spent per option in quadratic polls
+    pub allocations: Vec<VoiceCreditAllocation>,
+    /// Commitment of a vote that has not been revealed yet, see `vote_commitment`
+    pub commitment: Option<[u8; 32]>,
+    /// The delegate that voted on behalf of the voter. Such a receipt only records the
+    /// weight handed to the delegate, the votes are counted in the delegate's receipt.
+    pub delegate: Option<Pubkey>,
+    /// Receipts of the delegators the vote was cast on behalf of
+    pub delegator_count: u32,
+}
+
+impl VoteReceipt {
+    /// Votes the receipt adds to the tally, per option index.
+    pub fn tallied_votes(&self) -> Vec<(usize, u64)> {
+        if self.allocations.is_empty() {
+            selected_options(self.selections)
+                .map(|option| (option, self.weight))
+                .collect()
+        } else {
+            self.allocations
+                .iter()
+                .map(|allocation| (allocation.option as usize, allocation.votes()))
+                .collect()
+        }
+    }
+
+    pub fn voice_credits_spent(&self) -> u64 {
+        self.allocations
+            .iter()
+            .map(|allocation| allocation.credits)
+            .sum()
+    }
+}
+
+/// Voice credits a voter spends on an option of a quadratic poll.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct VoiceCreditAllocation {
+    pub option: u8,
+    pub credits: u64,
+}
+
+impl VoiceCreditAllocation {
+    /// Votes the credits buy, the integer square root of the credits.
+    pub fn votes(&self) -> u64 {
+        let credits = self.credits;
+        if credits < 2 {
+            return credits;
+        }
+
+        // Newton's method, converging on the root from above
+        let mut root = credits / 2 + 1;
+        let mut next = (root + credits / root) / 2;
+        while next < root {
+            root = next;
+            next = (root + credits / root) / 2;
+        }
+        root
+    }
+}
+
+/// Commitment to a secret vote: the keccak-256 hash of (option, salt, voter). The voter is
+/// part of the hash, so nobody can copy a commitment and reveal it after its owner did.
+pub fn vote_commitment(option: u8, salt: &[u8; 32], voter: &Pubkey) -> [u8; 32] {
+    keccak::hashv(&[&[option], salt, voter.as_ref()]).to_bytes()
+}
+
+/// Indexes of the options in a bitmask of selected options.
+pub fn selected_options(selections: u32) -> impl Iterator<Item = usize> {
+    (0..MAX_OPTIONS).filter(move |option| selections & (1 << option) != 0)
+}
+
+/// Governance tokens a voter locked with the program to vote in escrow weighted polls.
+///
+/// The tokens sit in a vault token account owned by this record and can only be
+/// withdrawn once every vote they back has been relinquished.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct VoterEscrow {
+    pub account_type: AccountType,
+    pub mint: Pubkey,
+    pub voter: Pubkey,
+    /// Tokens held in the vault, the weight of the voter's votes
+    pub amount: u64,
+    /// Votes cast with this escrow that have not been relinquished yet
+    pub active_votes: u32,
+    /// Realm proposals the escrow backs that have not been finalized or cancelled yet
+    pub active_proposals: u32,
+}
+
+impl VoterEscrow {
+    pub const LEN: usize = 1 + 32 + 32 + 8 + 4 + 4;
+}
+
+/// A set of wallets managed by its authority, polls can restrict voting to its members.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct VoterRegistry {
+    pub account_type: AccountType,
+    /// The only key allowed to add and remove members
+    pub authority: Pubkey,
+    pub registry_id: u64,
+    pub member_count: u32,
+}
+
+impl VoterRegistry {
+    pub const LEN: usize = 1 + 32 + 8 + 4;
+}
+
+/// Membership of a wallet in a voter registry, lives at the program derived address of
+/// (registry, member) so its existence alone proves membership.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct RegistryMember {
+    pub account_type: AccountType,
+    pub registry: Pubkey,
+    pub member: Pubkey,
+}
+
+impl RegistryMember {
+    pub const LEN: usize = 1 + 32 + 32;
+}
+
+/// An organization whose proposals share its governance mint and voting rules. Lives at
+/// the program derived address of its name, its proposals are polls at the program
+/// derived addresses of (realm, proposal index).
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct Realm {
+    pub account_type: AccountType,
+    pub name: String,
+    /// The only key allowed to manage the realm
+    pub authority: Pubkey,
+    /// Proposals are weighted by tokens of this mint locked in voter escrows
+    pub governance_mint: Pubkey,
+    /// Seconds proposals are open for voting from their start
+    pub voting_duration: u32,
+    pub quorum: Quorum,
+    pub threshold: Threshold,
+    /// Escrowed governance tokens needed to create a proposal
+    pub min_proposal_weight: u64,
+    /// Lamports locked in every proposal until it is resolved, forfeited to the realm
+    /// treasury when the authority cancels the proposal as spam
+    pub proposal_deposit: u64,
+    /// Proposals created so far, the index of the next proposal
+    pub proposal_count: u64,
+}
+
+/// Polls a delegation lets the delegate vote in.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum DelegationScope {
+    /// Every poll
+    #[default]
+    All,
+    /// A single poll
+    Poll { poll: Pubkey },
+    /// The proposals of a realm
+    Realm { realm: Pubkey },
+}
+
+impl DelegationScope {
+    /// Seed distinguishing the delegation addresses of one delegator.
+    pub fn seed(&self) -> &[u8] {
+        match self {
+            DelegationScope::All => &[],
+            DelegationScope::Poll { poll } => poll.as_ref(),
+            DelegationScope::Realm { realm } => realm.as_ref(),
+        }
+    }
+}
+
+/// Hands the vote weight of the delegator to the delegate, lives at the program derived
+/// address of (delegator, scope). The delegate votes with the summed weight of its
+/// delegators, whoever votes first in a poll uses the delegator's weight there.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct Delegation {
+    pub account_type: AccountType,
+    pub delegator: Pubkey,
+    pub delegate: Pubkey,
+    pub scope: DelegationScope,
+}
+
+impl Delegation {
+    /// Size of a delegation with the widest scope
+    pub const LEN: usize = 1 + 32 + 32 + 1 + 32;
+}
+
+/// Options of a ranked ballot in order of preference, with the weight of the vote.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct RankedBallot {
+    pub weight: u64,
+    pub ranking: Vec<u8>,
+}
+
+/// Ranked ballots of a poll, lives at the program derived address of (poll, index). Chunk
+/// `index` holds ballots `index * BALLOTS_PER_CHUNK` up to the next chunk.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct BallotChunk {
+    pub account_type: AccountType,
+    pub poll: Pubkey,
+    pub index: u32,
+    pub ballots: Vec<RankedBallot>,
+}
+
+impl BallotChunk {
+    /// Size of a full chunk of a poll with `option_count` options
+    pub fn space(option_count: usize) -> usize {
+        1 + 32 + 4 + 4 + BALLOTS_PER_CHUNK as usize * (8 + 4 + option_count)
+    }
+}
+
+/// Instant-runoff count of a ranked choice poll, advanced by `Tally` over as many
+/// transactions as it takes. Lives at the program derived address of the poll.
+///
+/// Every round counts each ballot for its most preferred option still in the race. An
+/// option with a majority of the counted ballots wins, otherwise the option with the fewest
+/// votes is eliminated and the next round starts. Ties favor the lower option index.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct RankedTally {
+    pub account_type: AccountType,
+    pub poll: Pubkey,
+    /// Options eliminated in earlier rounds, bit `i` for option `i`
+    pub eliminated: u32,
+    /// Ballot chunk the round in progress continues with
+    pub next_chunk: u32,
+    /// Counts of the round in progress
+    pub counts: Vec<u64>,
+    /// Counts of every completed round
+    pub rounds: Vec<Vec<u64>>,
+    pub winner: Option<u8>,
+    pub is_complete: bool,
+}
+
+impl RankedTally {
+    pub fn new(poll: Pubkey, option_count: usize) -> Self {
+        Self {
+            account_type: AccountType::RankedTally,
+            poll,
+            counts: vec![0; option_count],
+            ..Self::default()
+        }
+    }
+
+    /// Size of a tally of a poll with `option_count` options, after its last round
+    pub fn space(option_count: usize) -> usize {
+        let counts = 4 + 8 * option_count;
+        1 + 32 + 4 + 4 + counts + 4 + option_count * counts + 2 + 1
+    }
+
+    fn is_eliminated(&self, option: usize) -> bool {
+        self.eliminated & (1 << option) != 0
+    }
+
+    /// Counts the ballot for its most preferred option still in the race, if any.
+    pub fn count_ballot(&mut self, ballot: &RankedBallot) -> Result<(), VoteError> {
+        let continuing = ballot
+            .ranking
+            .iter()
+            .map(|option| *option as usize)
+            .find(|option| !self.is_eliminated(*option));
+        if let Some(option) = continuing {
+            let count = self
+                .counts
+                .get_mut(option)
+                .ok_or(VoteError::UnknownVoteOption)?;
+            *count = count
+                .checked_add(ballot.weight)
+                .ok_or(VoteError::CounterOverflow)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the round in progress once every ballot chunk has been counted, either
+    /// deciding the winner or eliminating the weakest option.
+    pub fn complete_round(&mut self) {
+        let option_count = self.counts.len();
+        let counts = std::mem::replace(&mut self.counts, vec![0; option_count]);
+        let total: u128 = counts.iter().map(|count| u128::from(*count)).sum();
+        let continuing: Vec<usize> = (0..counts.len())
+            .filter(|option| !self.is_eliminated(*option))
+            .collect();
+        self.rounds.push(counts.clone());
+        self.next_chunk = 0;
+
+        // Every ballot is exhausted, nobody can win
+        if total == 0 {
+            self.is_complete = true;
+            return;
+        }
+
+        let leader = continuing
+            .iter()
+            .copied()
+            .max_by(|a, b| counts[*a].cmp(&counts[*b]).then(b.cmp(a)));
+        if let Some(leader) = leader {
+            if u128::from(counts[leader]) * 2 > total {
+                self.winner = Some(leader as u8);
+                self.is_complete = true;
+                return;
+            }
+        }
+
+        let weakest = continuing
+            .iter()
+            .copied()
+            .min_by(|a, b| counts[*a].cmp(&counts[*b]).then(b.cmp(a)));
+        if let Some(weakest) = weakest {
+            self.eliminated |= 1 << weakest;
+        }
+        let mut remaining = continuing
+            .into_iter()
+            .filter(|option| !self.is_eliminated(*option));
+        if let (Some(last), None) = (remaining.next(), remaining.next()) {
+            self.winner = Some(last as u8);
+            self.is_complete = true;
+        }
+    }
+}
+
+/// An account an attached instruction is invoked with.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct AccountMetaData {
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+/// An instruction attached to a proposal, serialized until it is executed.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, PartialEq)]
+pub struct InstructionData {
+    pub program_id: Pubkey,
+    pub accounts: Vec<AccountMetaData>,
+    pub data: Vec<u8>,
+}
+
+impl From<Instruction> for InstructionData {
+    fn from(instruction: Instruction) -> Self {
+        Self {
+            program_id: instruction.program_id,
+            accounts: instruction
+                .accounts
+                .into_iter()
+                .map(|meta| AccountMetaData {
+                    pubkey: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: instruction.data,
+        }
+    }
+}
+
+impl From<&InstructionData> for Instruction {
+    fn from(instruction: &InstructionData) -> Self {
+        Self {
+            program_id: instruction.program_id,
+            accounts: instruction
+                .accounts
+                .iter()
+                .map(|meta| AccountMeta {
+                    pubkey: meta.pubkey,
+                    is_signer: meta.is_signer,
+                    is_writable: meta.is_writable,
+                })
+                .collect(),
+            data: instruction.data.clone(),
+        }
+    }
+}
+
+/// Instructions executed on behalf of the governance treasury once their poll has passed.
+///
+/// Lives at the program derived address of (poll, index), a poll can carry any number of
+/// them. Execution is allowed `hold_up_time` seconds after voting ended, until the
+/// `EXECUTION_GRACE_PERIOD` runs out.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Default, PartialEq)]
+pub struct ProposalTransaction {
+    pub account_type: AccountType,
+    pub poll: Pubkey,
+    pub index: u16,
+    pub hold_up_time: u32,
+    pub instructions: Vec<InstructionData>,
+    /// Unix timestamp the instructions were executed at
+    pub executed_at: Option<i64>,
+}
+
+impl ProposalTransaction {
+    /// Unix timestamp the transaction can be executed from once its poll passed.
+    pub fn executable_at(&self, poll: &Poll) -> i64 {
+        poll.tally_end_ts().saturating_add(self.hold_up_time.into())
+    }
+
+    /// Unix timestamp the transaction can no longer be executed from.
+    pub fn expires_at(&self, poll: &Poll) -> i64 {
+        self.executable_at(poll)
+            .saturating_add(EXECUTION_GRACE_PERIOD)
+    }
+}
+
+pub fn find_poll_address(program_id: &Pubkey, creator: &Pubkey, poll_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[POLL_SEED, creator.as_ref(), &poll_id.to_le_bytes()],
+        program_id,
+    )
+}
+
+pub fn find_realm_address(program_id: &Pubkey, name: &str) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[REALM_SEED, name.as_bytes()], program_id)
+}
+
+pub fn find_receipt_address(program_id: &Pubkey, poll: &Pubkey, voter: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RECEIPT_SEED, poll.as_ref(), voter.as_ref()], program_id)
+}
+
+pub fn find_escrow_address(program_id: &Pubkey, mint: &Pubkey, voter: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ESCROW_SEED, mint.as_ref(), voter.as_ref()], program_id)
+}
+
+pub fn find_escrow_vault_address(
+    program_id: &Pubkey,
+    mint: &Pubkey,
+    voter: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[ESCROW_VAULT_SEED, mint.as_ref(), voter.as_ref()],
+        program_id,
+    )
+}
+
+pub fn find_registry_address(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    registry_id: u64,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            REGISTRY_SEED,
+            authority.as_ref(),
+            &registry_id.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+pub fn find_member_address(
+    program_id: &Pubkey,
+    registry: &Pubkey,
+    member: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[MEMBER_SEED, registry.as_ref(), member.as_ref()],
+        program_id,
+    )
+}
+
+pub fn find_delegation_address(
+    program_id: &Pubkey,
+    delegator: &Pubkey,
+    scope: &DelegationScope,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[DELEGATION_SEED, delegator.as_ref(), scope.seed()],
+        program_id,
+    )
+}
+
+pub fn find_proposal_transaction_address(
+    program_id: &Pubkey,
+    poll: &Pubkey,
+    index: u16,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            PROPOSAL_TRANSACTION_SEED,
+            poll.as_ref(),
+            &index.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// The treasury signing the instructions of passed proposals of the polls of `authority`.
+pub fn find_treasury_address(program_id: &Pubkey, authority: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TREASURY_SEED, authority.as_ref()], program_id)
+}
+
+pub fn find_ballot_chunk_address(program_id: &Pubkey, poll: &Pubkey, index: u32) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[BALLOT_CHUNK_SEED, poll.as_ref(), &index.to_le_bytes()],
+        program_id,
+    )
+}
+
+pub fn find_ranked_tally_address(program_id: &Pubkey, poll: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[RANKED_TALLY_SEED, poll.as_ref()], program_id)
+}
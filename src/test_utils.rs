@@ -0,0 +1,226 @@
+//! Helpers for exercising the processor without a running validator.
+//!
+//! Sysvars and cross-program invocations are served by [`TestSyscallStubs`], which
+//! emulates the subset of the system program the vote program relies on and hands token
+//! program instructions to the token program processor.
+
+use std::{cell::Cell, sync::Once};
+
+use solana_program::{
+    account_info::AccountInfo,
+    clock::{Clock, Epoch, UnixTimestamp},
+    entrypoint::{ProgramResult, MAX_PERMITTED_DATA_INCREASE},
+    instruction::Instruction,
+    program_error::ProgramError,
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    program_utils::limited_deserialize,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction::SystemInstruction,
+    system_program,
+};
+
+thread_local! {
+    static UNIX_TIMESTAMP: Cell<UnixTimestamp> = const { Cell::new(0) };
+}
+
+/// Sets the unix timestamp reported by the clock sysvar to the current test.
+pub fn set_clock(unix_timestamp: UnixTimestamp) {
+    UNIX_TIMESTAMP.with(|cell| cell.set(unix_timestamp));
+}
+
+struct TestSyscallStubs;
+
+impl SyscallStubs for TestSyscallStubs {
+    fn sol_log(&self, _message: &str) {}
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        _signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        let account = |index: usize| {
+            let key = instruction.accounts[index].pubkey;
+            account_infos
+                .iter()
+                .find(|info| *info.key == key)
+                .ok_or(ProgramError::NotEnoughAccountKeys)
+        };
+
+        if instruction.program_id == spl_token::id() {
+            // The runtime marks accounts signed through program seeds as signers
+            let accounts = instruction
+                .accounts
+                .iter()
+                .enumerate()
+                .map(|(index, meta)| {
+                    let mut info = account(index)?.clone();
+                    info.is_signer |= meta.is_signer;
+                    Ok(info)
+                })
+                .collect::<Result<Vec<_>, ProgramError>>()?;
+            return spl_token::processor::Processor::process(
+                &instruction.program_id,
+                &accounts,
+                &instruction.data,
+            );
+        }
+        if instruction.program_id != system_program::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        match limited_deserialize(&instruction.data, 1024)
+            .map_err(|_| ProgramError::InvalidInstructionData)?
+        {
+            SystemInstruction::CreateAccount {
+                lamports,
+                space,
+                owner,
+            } => {
+                let (from, to) = (account(0)?, account(1)?);
+                if to.lamports() > 0 {
+                    return Err(ProgramError::AccountAlreadyInitialized);
+                }
+                transfer_lamports(from, to, lamports)?;
+                allocate(to, space);
+                to.assign(&owner);
+            }
+            SystemInstruction::Transfer { lamports } => {
+                transfer_lamports(account(0)?, account(1)?, lamports)?;
+            }
+            SystemInstruction::Assign { owner } => account(0)?.assign(&owner),
+            SystemInstruction::Allocate { space } => allocate(account(0)?, space),
+            _ => return Err(ProgramError::InvalidInstructionData),
+        }
+
+        Ok(())
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        let clock = Clock {
+            unix_timestamp: UNIX_TIMESTAMP.with(Cell::get),
+            ..Clock::default()
+        };
+        unsafe { *(var_addr as *mut Clock) = clock };
+        solana_program::entrypoint::SUCCESS
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        unsafe { *(var_addr as *mut Rent) = Rent::default() };
+        solana_program::entrypoint::SUCCESS
+    }
+}
+
+/// Swaps in a zeroed buffer of `space` bytes.
+fn allocate(account: &AccountInfo, space: u64) {
+    *account.data.borrow_mut() = leak_data(vec![0; space as usize]);
+}
+
+/// Leaks `data` laid out like the runtime's serialized input: preceded by its length and
+/// followed by room to grow, which `AccountInfo::realloc` relies on.
+fn leak_data(data: Vec<u8>) -> &'static mut [u8] {
+    let mut buffer = vec![0; 8 + data.len() + MAX_PERMITTED_DATA_INCREASE];
+    buffer[..8].copy_from_slice(&(data.len() as u64).to_le_bytes());
+    buffer[8..8 + data.len()].copy_from_slice(&data);
+    &mut Box::leak(buffer.into_boxed_slice())[8..8 + data.len()]
+}
+
+fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    let mut from_lamports = from.try_borrow_mut_lamports()?;
+    **from_lamports = from_lamports
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    **to.try_borrow_mut_lamports()? += lamports;
+    Ok(())
+}
+
+/// Installs the test syscall stubs, safe to call from every test.
+pub fn setup() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        set_syscall_stubs(Box::new(TestSyscallStubs));
+    });
+}
+
+/// Account backed by leaked storage, so infos can be cloned freely across a test.
+fn leaked_account(
+    key: Pubkey,
+    is_signer: bool,
+    lamports: u64,
+    data: Vec<u8>,
+    owner: Pubkey,
+) -> AccountInfo<'static> {
+    AccountInfo::new(
+        Box::leak(Box::new(key)),
+        is_signer,
+        true,
+        Box::leak(Box::new(lamports)),
+        leak_data(data),
+        Box::leak(Box::new(owner)),
+        false,
+        Epoch::default(),
+    )
+}
+
+/// A rent exempt account holding `data`.
+pub fn program_account(key: Pubkey, owner: Pubkey, data: Vec<u8>) -> AccountInfo<'static> {
+    let lamports = Rent::default().minimum_balance(data.len());
+    leaked_account(key, false, lamports, data, owner)
+}
+
+/// A signing, lamport holding wallet owned by the system program.
+pub fn wallet() -> AccountInfo<'static> {
+    leaked_account(
+        Pubkey::new_unique(),
+        true,
+        1_000_000_000,
+        vec![],
+        system_program::id(),
+    )
+}
+
+/// A not yet created account at `key`.
+pub fn uncreated(key: Pubkey) -> AccountInfo<'static> {
+    leaked_account(key, false, 0, vec![], system_program::id())
+}
+
+pub fn system_program_account() -> AccountInfo<'static> {
+    leaked_account(system_program::id(), false, 0, vec![], Pubkey::default())
+}
+
+/// An initialized token account of `mint` owned by `owner`.
+pub fn token_account(mint: &Pubkey, owner: &Pubkey, amount: u64) -> AccountInfo<'static> {
+    let mut data = vec![0; spl_token::state::Account::LEN];
+    spl_token::state::Account {
+        mint: *mint,
+        owner: *owner,
+        amount,
+        state: spl_token::state::AccountState::Initialized,
+        ..spl_token::state::Account::default()
+    }
+    .pack_into_slice(&mut data);
+
+    program_account(Pubkey::new_unique(), spl_token::id(), data)
+}
+
+/// An initialized mint without a mint authority.
+pub fn mint_account(supply: u64) -> AccountInfo<'static> {
+    let mut data = vec![0; spl_token::state::Mint::LEN];
+    spl_token::state::Mint {
+        supply,
+        is_initialized: true,
+        ..spl_token::state::Mint::default()
+    }
+    .pack_into_slice(&mut data);
+
+    program_account(Pubkey::new_unique(), spl_token::id(), data)
+}
+
+/// Balance of a token account.
+pub fn token_amount(account: &AccountInfo) -> u64 {
+    spl_token::state::Account::unpack(&account.data.borrow())
+        .unwrap()
+        .amount
+}
@@ -0,0 +1,95 @@
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
+};
+
+/// Creates a rent exempt account at a program derived address owned by `owner`.
+///
+/// The address may already hold lamports (anyone can transfer to it), in which case the
+/// balance is topped up and the account is allocated and assigned instead of created.
+pub fn create_pda_account<'a>(
+    payer: &AccountInfo<'a>,
+    new_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    owner: &Pubkey,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> ProgramResult {
+    let rent = Rent::get()?;
+    let required_lamports = rent.minimum_balance(space);
+
+    if new_account.lamports() == 0 {
+        return invoke_signed(
+            &system_instruction::create_account(
+                payer.key,
+                new_account.key,
+                required_lamports,
+                space as u64,
+                owner,
+            ),
+            &[payer.clone(), new_account.clone(), system_program.clone()],
+            &[signer_seeds],
+        );
+    }
+
+    let top_up = required_lamports.saturating_sub(new_account.lamports());
+    if top_up > 0 {
+        invoke(
+            &system_instruction::transfer(payer.key, new_account.key, top_up),
+            &[payer.clone(), new_account.clone(), system_program.clone()],
+        )?;
+    }
+
+    invoke_signed(
+        &system_instruction::allocate(new_account.key, space as u64),
+        &[new_account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )?;
+
+    invoke_signed(
+        &system_instruction::assign(new_account.key, owner),
+        &[new_account.clone(), system_program.clone()],
+        &[signer_seeds],
+    )
+}
+
+/// Moves lamports out of a program owned account.
+pub fn transfer_lamports(from: &AccountInfo, to: &AccountInfo, lamports: u64) -> ProgramResult {
+    let from_lamports = from
+        .lamports()
+        .checked_sub(lamports)
+        .ok_or(ProgramError::InsufficientFunds)?;
+    let to_lamports = to
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::InvalidArgument)?;
+    **from.try_borrow_mut_lamports()? = from_lamports;
+    **to.try_borrow_mut_lamports()? = to_lamports;
+
+    Ok(())
+}
+
+/// Closes a program owned account, refunding its lamports to `destination`.
+///
+/// The data is zeroed and handed back to the system program, so the address can be
+/// created again within the same transaction.
+pub fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = destination
+        .lamports()
+        .checked_add(account.lamports())
+        .ok_or(ProgramError::InvalidArgument)?;
+    **destination.try_borrow_mut_lamports()? = lamports;
+    **account.try_borrow_mut_lamports()? = 0;
+
+    account.data.borrow_mut().fill(0);
+    account.realloc(0, false)?;
+    account.assign(&system_program::id());
+
+    Ok(())
+}